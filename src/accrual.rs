@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::chat::{ChatMessage, Membership, Role};
+
+/* Passive points - viewers earn points just for being around.
+ *
+ * Every `interval` seconds, everyone who chatted during the interval gets `active`
+ * points, and everyone else who's in chat (from JOIN/PART and NAMES) gets `present` points.
+ * Roles multiply that, the best multiplier a viewer has wins. With `live_only`, points
+ * are only given while a mod has marked the stream as live.
+ *
+ * Configured by the "accrual" section of game.json, off unless enabled:
+ *
+ *  "accrual": { "enabled": true, "interval": 600, "active": 10, "present": 2,
+ *               "multipliers": { "subscriber": 2.0, "vip": 1.5 }, "live_only": true }
+ */
+
+fn get_false_lol() -> bool {
+    false
+}
+fn default_interval() -> u64 {
+    600
+}
+fn default_active() -> i64 {
+    10
+}
+fn default_present() -> i64 {
+    2
+}
+fn default_multipliers() -> HashMap<Role, f64> {
+    let mut multipliers = HashMap::new();
+    multipliers.insert(Role::Subscriber, 2.0);
+    multipliers
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccrualConfig {
+    #[serde(default = "get_false_lol")]
+    pub enabled: bool,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    #[serde(default = "default_active")]
+    pub active: i64,
+    #[serde(default = "default_present")]
+    pub present: i64,
+    #[serde(default = "default_multipliers")]
+    pub multipliers: HashMap<Role, f64>,
+    #[serde(default = "get_false_lol")]
+    pub live_only: bool,
+}
+
+impl Default for AccrualConfig {
+    fn default() -> AccrualConfig {
+        AccrualConfig::new()
+    }
+}
+
+impl AccrualConfig {
+    pub fn new() -> AccrualConfig {
+        AccrualConfig {
+            enabled: false,
+            interval: default_interval(),
+            active: default_active(),
+            present: default_present(),
+            multipliers: default_multipliers(),
+            live_only: false,
+        }
+    }
+
+    fn multiplier(&self, roles: Option<&Vec<Role>>) -> f64 {
+        roles
+            .into_iter()
+            .flatten()
+            .filter_map(|r| self.multipliers.get(r))
+            .cloned()
+            .fold(1.0, f64::max)
+    }
+}
+
+pub struct Presence {
+    // Chatted since the last payout
+    active: HashSet<String>,
+    // In chat, as far as Twitch told us
+    present: HashSet<String>,
+    // The roles people had the last time they chatted
+    roles: HashMap<String, Vec<Role>>,
+    live: bool,
+    next: Option<u64>,
+}
+
+impl Default for Presence {
+    fn default() -> Presence {
+        Presence::new()
+    }
+}
+
+impl Presence {
+    pub fn new() -> Presence {
+        Presence {
+            active: HashSet::new(),
+            present: HashSet::new(),
+            roles: HashMap::new(),
+            live: false,
+            next: None,
+        }
+    }
+
+    pub fn seen(&mut self, msg: &ChatMessage) {
+        self.active.insert(msg.name.clone());
+        self.present.insert(msg.name.clone());
+        self.roles.insert(msg.name.clone(), msg.roles());
+    }
+
+    pub fn update(&mut self, membership: Membership) {
+        match membership {
+            Membership::Join(user) => {
+                self.present.insert(user);
+            }
+            Membership::Part(user) => {
+                self.present.remove(&user);
+            }
+            Membership::Names(users) => self.present.extend(users),
+        }
+    }
+
+    pub fn set_live(&mut self, live: bool) {
+        self.live = live;
+    }
+
+    pub fn live(&self) -> bool {
+        self.live
+    }
+
+    // Points to give out, if an interval just ended.
+    pub fn tick(&mut self, config: &AccrualConfig, now: u64) -> Vec<(String, i64)> {
+        let next = match self.next {
+            Some(next) if config.enabled => next,
+            _ => {
+                // Start counting from when accrual gets turned on.
+                self.next = Some(now + config.interval);
+                self.active.clear();
+                return Vec::new();
+            }
+        };
+        if now < next {
+            return Vec::new();
+        }
+        self.next = Some(now + config.interval);
+        let active: HashSet<String> = self.active.drain().collect();
+        if config.live_only && !self.live {
+            return Vec::new();
+        }
+        let mut awards: Vec<(String, i64)> = self
+            .present
+            .union(&active)
+            .map(|user| {
+                let base = if active.contains(user) {
+                    config.active
+                } else {
+                    config.present
+                };
+                let points = base as f64 * config.multiplier(self.roles.get(user));
+                (user.clone(), points.round() as i64)
+            })
+            .filter(|(_, points)| *points > 0)
+            .collect();
+        awards.sort();
+        awards
+    }
+}
+
+#[cfg(test)]
+mod accrual_tests {
+    use super::*;
+
+    fn msg(name: &str, badges: &str) -> ChatMessage {
+        let mut msg = ChatMessage::new(
+            &String::from(name),
+            &String::from("desktopfolder"),
+            &String::from("hi"),
+        );
+        msg.tags
+            .insert(String::from("badges"), String::from(badges));
+        msg
+    }
+
+    #[test]
+    fn test_accrual() {
+        let mut config = AccrualConfig::new();
+        config.enabled = true;
+        config.interval = 60;
+        let mut p = Presence::new();
+        assert!(p.tick(&config, 0).is_empty());
+        p.update(Membership::Names(vec![
+            String::from("lurker"),
+            String::from("gone"),
+        ]));
+        p.update(Membership::Part(String::from("gone")));
+        p.seen(&msg("chatter", ""));
+        p.seen(&msg("sub", "subscriber/3"));
+        assert!(p.tick(&config, 59).is_empty());
+        assert_eq!(
+            p.tick(&config, 60),
+            vec![
+                (String::from("chatter"), 10),
+                (String::from("lurker"), 2),
+                (String::from("sub"), 20),
+            ]
+        );
+        // Nobody chatted this time around.
+        assert_eq!(
+            p.tick(&config, 120),
+            vec![
+                (String::from("chatter"), 2),
+                (String::from("lurker"), 2),
+                (String::from("sub"), 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_live_only() {
+        let mut config = AccrualConfig::new();
+        config.enabled = true;
+        config.live_only = true;
+        let mut p = Presence::new();
+        p.tick(&config, 0);
+        p.seen(&msg("chatter", ""));
+        assert!(p.tick(&config, 600).is_empty());
+        p.set_live(true);
+        p.seen(&msg("chatter", ""));
+        assert_eq!(p.tick(&config, 1200), vec![(String::from("chatter"), 10)]);
+    }
+}
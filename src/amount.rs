@@ -0,0 +1,77 @@
+/* Amounts of points, as typed in chat.
+ *
+ * Besides plain numbers, these all work, relative to how many points the player has:
+ *   all, half, 50%, 1k, 2.5k, 1m
+ * Anything that doesn't come out whole is rounded down, so nobody bets a point they don't have.
+ */
+
+pub fn parse_amount(text: &str, cash: i64) -> Result<i64, String> {
+    let text = text.trim().to_lowercase();
+    let cash = cash.max(0);
+    let amount = match text.as_str() {
+        "" => return Err(String::from("You need to say how many points!")),
+        "all" | "allin" | "all-in" => cash as f64,
+        "half" => cash as f64 / 2.0,
+        _ => {
+            let (number, scale) = match text.chars().last() {
+                Some('%') => (&text[..text.len() - 1], None),
+                Some('k') => (&text[..text.len() - 1], Some(1_000.0)),
+                Some('m') => (&text[..text.len() - 1], Some(1_000_000.0)),
+                _ => (text.as_str(), Some(1.0)),
+            };
+            let number = match number.parse::<f64>() {
+                Ok(n) if n.is_finite() => n,
+                _ => {
+                    return Err(String::from(
+                        "That's not an amount! Try a number, all, half, 50% or 2.5k.",
+                    ))
+                }
+            };
+            match scale {
+                Some(scale) => number * scale,
+                None if number <= 0.0 || number > 100.0 => {
+                    return Err(String::from("Percentages have to be between 0% and 100%!"))
+                }
+                None => cash as f64 * number / 100.0,
+            }
+        }
+    };
+    if amount < 0.0 {
+        return Err(String::from("Amounts can't be negative!"));
+    }
+    if amount >= i64::MAX as f64 {
+        return Err(String::from("That's more points than exist!"));
+    }
+    Ok(amount.floor() as i64)
+}
+
+#[cfg(test)]
+mod amount_tests {
+    use super::*;
+
+    #[test]
+    fn test_amounts() {
+        assert_eq!(parse_amount("250", 1000), Ok(250));
+        assert_eq!(parse_amount("all", 1001), Ok(1001));
+        assert_eq!(parse_amount("HALF", 1001), Ok(500));
+        assert_eq!(parse_amount("50%", 1001), Ok(500));
+        assert_eq!(parse_amount("12.5%", 100), Ok(12));
+        assert_eq!(parse_amount("1k", 0), Ok(1000));
+        assert_eq!(parse_amount("2.5k", 0), Ok(2500));
+        assert_eq!(parse_amount("1.5m", 0), Ok(1500000));
+        assert_eq!(parse_amount("7.9", 0), Ok(7));
+        assert_eq!(parse_amount("all", -20), Ok(0));
+    }
+
+    #[test]
+    fn test_bad_amounts() {
+        assert!(parse_amount("", 100).is_err());
+        assert!(parse_amount("lots", 100).is_err());
+        assert!(parse_amount("k", 100).is_err());
+        assert!(parse_amount("150%", 100).is_err());
+        assert!(parse_amount("0%", 100).is_err());
+        assert!(parse_amount("-5", 100).is_err());
+        assert!(parse_amount("nan", 100).is_err());
+        assert!(parse_amount("1e30", 100).is_err());
+    }
+}
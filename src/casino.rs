@@ -0,0 +1,283 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/* Casino minigames - slots and roulette, played with Game points.
+ *
+ * Payouts are multipliers of the bet, stake included: 2 means you get double back,
+ * 0.5 means you get half back. Configured by the "casino" section of game.json:
+ *
+ *  "casino": { "cooldown": 30, "pair": 0.5, "color": 2.0, "number": 36.0,
+ *              "slots": [ { "symbol": "🍒", "weight": 6, "payout": 5.0 }, ... ] }
+ *
+ * Slots pays the symbol's payout for three of a kind, and "pair" for any two.
+ * Roulette is a single zero wheel, bet on red, black or a number from 0 to 36.
+ * Everyone has to wait `cooldown` seconds between games.
+ *
+ * Casino::seeded gives the same spins every time, for tests.
+ */
+
+fn default_cooldown() -> u64 {
+    30
+}
+fn default_pair() -> f64 {
+    0.5
+}
+fn default_color() -> f64 {
+    2.0
+}
+fn default_number() -> f64 {
+    36.0
+}
+fn default_slots() -> Vec<SlotSymbol> {
+    vec![
+        SlotSymbol::new("🍒", 6, 5.0),
+        SlotSymbol::new("🍋", 5, 8.0),
+        SlotSymbol::new("🔔", 4, 12.0),
+        SlotSymbol::new("⭐", 3, 20.0),
+        SlotSymbol::new("💎", 1, 100.0),
+    ]
+}
+
+const REELS: usize = 3;
+const RED: [u32; 18] = [
+    1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotSymbol {
+    pub symbol: String,
+    // How often it comes up compared to the others
+    pub weight: u32,
+    // For three of a kind
+    pub payout: f64,
+}
+
+impl SlotSymbol {
+    fn new(symbol: &str, weight: u32, payout: f64) -> SlotSymbol {
+        SlotSymbol {
+            symbol: symbol.to_string(),
+            weight,
+            payout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CasinoConfig {
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    #[serde(default = "default_slots")]
+    pub slots: Vec<SlotSymbol>,
+    #[serde(default = "default_pair")]
+    pub pair: f64,
+    #[serde(default = "default_color")]
+    pub color: f64,
+    #[serde(default = "default_number")]
+    pub number: f64,
+}
+
+impl Default for CasinoConfig {
+    fn default() -> CasinoConfig {
+        CasinoConfig::new()
+    }
+}
+
+impl CasinoConfig {
+    pub fn new() -> CasinoConfig {
+        CasinoConfig {
+            cooldown: default_cooldown(),
+            slots: default_slots(),
+            pair: default_pair(),
+            color: default_color(),
+            number: default_number(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouletteBet {
+    Red,
+    Black,
+    Number(u32),
+}
+
+impl RouletteBet {
+    pub fn parse(text: &str) -> Option<RouletteBet> {
+        match text.trim().to_lowercase().as_str() {
+            "red" => Some(RouletteBet::Red),
+            "black" => Some(RouletteBet::Black),
+            n => match n.parse::<u32>() {
+                Ok(n) if n <= 36 => Some(RouletteBet::Number(n)),
+                _ => None,
+            },
+        }
+    }
+}
+
+pub fn color(number: u32) -> &'static str {
+    if number == 0 {
+        "green"
+    } else if RED.contains(&number) {
+        "red"
+    } else {
+        "black"
+    }
+}
+
+// Points back for a bet, stake included.
+fn pays(amount: i64, multiplier: f64) -> i64 {
+    (amount as f64 * multiplier.max(0.0)).floor() as i64
+}
+
+pub fn slots_payout(reels: &[usize], amount: i64, config: &CasinoConfig) -> i64 {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for r in reels {
+        *counts.entry(*r).or_insert(0) += 1;
+    }
+    match counts.iter().max_by_key(|(_, n)| **n) {
+        Some((symbol, n)) if *n == REELS => match config.slots.get(*symbol) {
+            Some(s) => pays(amount, s.payout),
+            None => 0,
+        },
+        Some((_, 2)) => pays(amount, config.pair),
+        _ => 0,
+    }
+}
+
+pub fn roulette_payout(bet: RouletteBet, number: u32, amount: i64, config: &CasinoConfig) -> i64 {
+    match bet {
+        RouletteBet::Red if color(number) == "red" => pays(amount, config.color),
+        RouletteBet::Black if color(number) == "black" => pays(amount, config.color),
+        RouletteBet::Number(n) if n == number => pays(amount, config.number),
+        _ => 0,
+    }
+}
+
+#[derive(Debug)]
+pub struct Casino {
+    rng: StdRng,
+    // User => unix time they last played
+    played: HashMap<String, u64>,
+}
+
+impl Default for Casino {
+    fn default() -> Casino {
+        Casino::new()
+    }
+}
+
+impl Casino {
+    pub fn new() -> Casino {
+        Casino {
+            rng: StdRng::from_entropy(),
+            played: HashMap::new(),
+        }
+    }
+
+    pub fn seeded(seed: u64) -> Casino {
+        Casino {
+            rng: StdRng::seed_from_u64(seed),
+            played: HashMap::new(),
+        }
+    }
+
+    // Seconds until the user can play again, if they have to wait.
+    pub fn cooldown(&self, user: &String, config: &CasinoConfig, now: u64) -> Option<u64> {
+        match self.played.get(user) {
+            Some(last) if last + config.cooldown > now => Some(last + config.cooldown - now),
+            _ => None,
+        }
+    }
+
+    pub fn played(&mut self, user: &String, now: u64) {
+        self.played.insert(user.clone(), now);
+    }
+
+    // Indices into config.slots, one per reel.
+    pub fn spin_slots(&mut self, config: &CasinoConfig) -> Vec<usize> {
+        let total: u32 = config.slots.iter().map(|s| s.weight).sum();
+        (0..REELS)
+            .map(|_| {
+                let mut roll = self.rng.gen_range(0..total.max(1));
+                config
+                    .slots
+                    .iter()
+                    .position(|s| {
+                        if roll < s.weight {
+                            true
+                        } else {
+                            roll -= s.weight;
+                            false
+                        }
+                    })
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    pub fn spin_roulette(&mut self) -> u32 {
+        self.rng.gen_range(0..37)
+    }
+}
+
+#[cfg(test)]
+mod casino_tests {
+    use super::*;
+
+    #[test]
+    fn test_slots() {
+        let config = CasinoConfig::new();
+        assert_eq!(slots_payout(&[4, 4, 4], 10, &config), 1000);
+        assert_eq!(slots_payout(&[0, 0, 0], 10, &config), 50);
+        assert_eq!(slots_payout(&[1, 3, 1], 10, &config), 5);
+        assert_eq!(slots_payout(&[0, 1, 2], 10, &config), 0);
+
+        // The same seed spins the same way.
+        let (mut a, mut b) = (Casino::seeded(7), Casino::seeded(7));
+        for _ in 0..20 {
+            let spin = a.spin_slots(&config);
+            assert_eq!(spin.len(), 3);
+            assert!(spin.iter().all(|s| *s < config.slots.len()));
+            assert_eq!(spin, b.spin_slots(&config));
+        }
+    }
+
+    #[test]
+    fn test_roulette() {
+        let config = CasinoConfig::new();
+        assert_eq!(RouletteBet::parse("RED"), Some(RouletteBet::Red));
+        assert_eq!(RouletteBet::parse("17"), Some(RouletteBet::Number(17)));
+        assert_eq!(RouletteBet::parse("37"), None);
+        assert_eq!(RouletteBet::parse("green"), None);
+        assert_eq!(color(0), "green");
+        assert_eq!(color(1), "red");
+        assert_eq!(color(2), "black");
+        assert_eq!(roulette_payout(RouletteBet::Red, 1, 10, &config), 20);
+        assert_eq!(roulette_payout(RouletteBet::Black, 1, 10, &config), 0);
+        assert_eq!(roulette_payout(RouletteBet::Black, 0, 10, &config), 0);
+        assert_eq!(roulette_payout(RouletteBet::Number(0), 0, 10, &config), 360);
+
+        let (mut a, mut b) = (Casino::seeded(42), Casino::seeded(42));
+        for _ in 0..20 {
+            let number = a.spin_roulette();
+            assert!(number <= 36);
+            assert_eq!(number, b.spin_roulette());
+        }
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let mut config = CasinoConfig::new();
+        let mut casino = Casino::seeded(0);
+        let user = String::from("mjb");
+        assert_eq!(casino.cooldown(&user, &config, 100), None);
+        casino.played(&user, 100);
+        assert_eq!(casino.cooldown(&user, &config, 110), Some(20));
+        assert_eq!(casino.cooldown(&user, &config, 130), None);
+        config.cooldown = 0;
+        casino.played(&user, 200);
+        assert_eq!(casino.cooldown(&user, &config, 200), None);
+    }
+}
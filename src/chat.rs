@@ -0,0 +1,194 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/* Chat messages, as read from Twitch IRC.
+ *
+ * With the twitch.tv/tags capability, every PRIVMSG is prefixed with tags:
+ *   @badges=moderator/1;id=abc-123;mod=1 :name!name@name.tmi.twitch.tv PRIVMSG #channel :hi
+ * Tags are optional here, so plain lines (and tests) still parse.
+ *
+ * With the twitch.tv/membership capability, we also hear who joins and leaves,
+ * plus a NAMES list (353) of who was already there when we joined.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Broadcaster,
+    Moderator,
+    Vip,
+    Subscriber,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Membership {
+    Join(String),
+    Part(String),
+    Names(Vec<String>),
+}
+
+pub fn parse_membership(line: &str) -> Option<Membership> {
+    lazy_static! {
+        static ref MEMBER_RE: Regex =
+            Regex::new(r"^:(\w*)!\w*@\w*\.tmi\.twitch\.tv (JOIN|PART) #\w*").unwrap();
+        static ref NAMES_RE: Regex = Regex::new(r"^:\S* 353 \S* = #\w* :(.*)").unwrap();
+    }
+    if let Some(caps) = MEMBER_RE.captures(line) {
+        let name = caps[1].to_string();
+        return Some(match &caps[2] {
+            "JOIN" => Membership::Join(name),
+            _ => Membership::Part(name),
+        });
+    }
+    let caps = NAMES_RE.captures(line)?;
+    Some(Membership::Names(
+        caps[1].split_whitespace().map(String::from).collect(),
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub name: String,
+    pub channel: String,
+    pub text: String,
+    pub tags: HashMap<String, String>,
+}
+
+fn unescape_tag(value: &str) -> String {
+    let mut res = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => res.push(';'),
+            Some('s') => res.push(' '),
+            Some('r') => res.push('\r'),
+            Some('n') => res.push('\n'),
+            Some(x) => res.push(x),
+            None => {}
+        }
+    }
+    res
+}
+
+impl ChatMessage {
+    pub fn new(name: &String, channel: &String, text: &String) -> ChatMessage {
+        ChatMessage {
+            name: name.clone(),
+            channel: channel.clone(),
+            text: text.clone(),
+            tags: HashMap::new(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<ChatMessage> {
+        lazy_static! {
+            static ref PRIV_RE: Regex =
+                Regex::new(r"^(?:@(\S*) )?:(\w*)!\w*@\w*\.tmi\.twitch\.tv PRIVMSG #(\w*) :\s*(.*)")
+                    .unwrap();
+        }
+        let caps = PRIV_RE.captures(line)?;
+        let mut tags = HashMap::new();
+        if let Some(t) = caps.get(1) {
+            for tag in t.as_str().split(';') {
+                let mut kv = tag.splitn(2, '=');
+                let key = kv.next().unwrap_or("");
+                if !key.is_empty() {
+                    tags.insert(key.to_string(), unescape_tag(kv.next().unwrap_or("")));
+                }
+            }
+        }
+        Some(ChatMessage {
+            name: caps[2].to_string(),
+            channel: caps[3].to_string(),
+            text: caps[4].trim_end().to_string(),
+            tags,
+        })
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&String> {
+        self.tags.get(key).filter(|v| !v.is_empty())
+    }
+
+    pub fn roles(&self) -> Vec<Role> {
+        let mut roles = Vec::new();
+        if let Some(badges) = self.tag("badges") {
+            for badge in badges.split(',') {
+                match badge.split('/').next().unwrap_or("") {
+                    "broadcaster" => roles.push(Role::Broadcaster),
+                    "moderator" => roles.push(Role::Moderator),
+                    "vip" => roles.push(Role::Vip),
+                    "subscriber" | "founder" => roles.push(Role::Subscriber),
+                    _ => {}
+                }
+            }
+        }
+        // Without tags, we can still tell who the broadcaster is.
+        if self.name == self.channel && !roles.contains(&Role::Broadcaster) {
+            roles.push(Role::Broadcaster);
+        }
+        roles
+    }
+
+    pub fn has_any_role(&self, roles: &[Role]) -> bool {
+        self.roles().iter().any(|r| roles.contains(r))
+    }
+}
+
+#[cfg(test)]
+mod chat_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain() {
+        let msg =
+            ChatMessage::parse(":mjb!mjb@mjb.tmi.twitch.tv PRIVMSG #desktopfolder :  hi there\r\n")
+                .unwrap();
+        assert_eq!(msg.name, "mjb");
+        assert_eq!(msg.channel, "desktopfolder");
+        assert_eq!(msg.text, "hi there");
+        assert!(msg.roles().is_empty());
+        assert!(ChatMessage::parse("PING :tmi.twitch.tv").is_none());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let msg = ChatMessage::parse(
+            "@badges=moderator/1,subscriber/12;id=abc-123;display-name=MJB\\shi;emotes= :mjb!mjb@mjb.tmi.twitch.tv PRIVMSG #desktopfolder :hi",
+        )
+        .unwrap();
+        assert_eq!(msg.tag("id").unwrap(), "abc-123");
+        assert_eq!(msg.tag("display-name").unwrap(), "MJB hi");
+        assert!(msg.tag("emotes").is_none());
+        assert_eq!(msg.roles(), vec![Role::Moderator, Role::Subscriber]);
+        assert!(msg.has_any_role(&[Role::Vip, Role::Moderator]));
+        assert!(!msg.has_any_role(&[Role::Vip]));
+    }
+
+    #[test]
+    fn test_membership() {
+        assert_eq!(
+            parse_membership(":mjb!mjb@mjb.tmi.twitch.tv JOIN #desktopfolder"),
+            Some(Membership::Join(String::from("mjb")))
+        );
+        assert_eq!(
+            parse_membership(":mjb!mjb@mjb.tmi.twitch.tv PART #desktopfolder\r\n"),
+            Some(Membership::Part(String::from("mjb")))
+        );
+        assert_eq!(
+            parse_membership(":bot.tmi.twitch.tv 353 bot = #desktopfolder :mjb other\r\n"),
+            Some(Membership::Names(vec![
+                String::from("mjb"),
+                String::from("other")
+            ]))
+        );
+        assert!(
+            parse_membership(":mjb!mjb@mjb.tmi.twitch.tv PRIVMSG #desktopfolder :JOIN").is_none()
+        );
+    }
+}
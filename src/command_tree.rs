@@ -1,16 +1,17 @@
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::Read;
 use std::iter::Peekable;
 use std::path::Path;
 use std::str::Split;
+use unicode_normalization::UnicodeNormalization;
 
 /* CommandTree - A (strange) tree implementation.
  *
  * Goals of this data structure:
  *  - Lookup time should be more or less as fast as is reasonable
- *  - Lookups should be case insensitive (lazily for now)
+ *  - Lookups should be case insensitive (see normalize)
  *  - Insertion and deletion time are nearly irrelevant
  *  - Lookup should return partial matches if there is no part of the search string that is 'wrong'
  *
@@ -25,6 +26,44 @@ use std::str::Split;
  * haha yes this is very incomplete you're correct!
  */
 
+/* Command names are compared after NFKC normalization and Unicode case folding,
+ * so "HELLO", "Hello" and full-width "ＨＥＬＬＯ" are all the same command.
+ * This is applied to keys when loading, and to each word when looking up.
+ * Alias targets are left as written (they may carry arguments) and get
+ * normalized word by word as they're looked up.
+ */
+pub fn normalize(name: &str) -> String {
+    let folded = caseless::default_case_fold_str(&name.nfkc().collect::<String>());
+    folded.nfkc().collect()
+}
+
+// When names collide, the first one in sorted order is kept, so the same file always
+// loads the same way (HashMap order changes from run to run).
+fn normalize_keys(commands: HashMap<String, CommandNode>) -> HashMap<String, CommandNode> {
+    let mut commands: Vec<(String, CommandNode)> = commands.into_iter().collect();
+    commands.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut res = HashMap::new();
+    for (name, mut node) in commands {
+        let key = normalize(&name);
+        if res.contains_key(&key) {
+            println!(
+                "Warning: '{}' collides with another command once normalized, ignoring it.",
+                name
+            );
+            continue;
+        }
+        node.subcommands = normalize_keys(node.subcommands);
+        res.insert(key, node);
+    }
+    res
+}
+
+fn keys_normalized(commands: &HashMap<String, CommandNode>) -> bool {
+    commands
+        .iter()
+        .all(|(name, node)| *name == normalize(name) && keys_normalized(&node.subcommands))
+}
+
 // lol
 fn get_true_lol() -> bool {
     true
@@ -32,6 +71,9 @@ fn get_true_lol() -> bool {
 fn get_false_lol() -> bool {
     false
 }
+fn get_zero_lol() -> u64 {
+    0
+}
 fn default_ver() -> String {
     "0.0.0".to_string()
 }
@@ -41,15 +83,96 @@ fn default_host() -> String {
 fn default_port() -> String {
     "6667".to_string()
 }
+fn default_prefixes() -> Vec<String> {
+    vec!["bot ".to_string(), "!".to_string(), "~".to_string()]
+}
+
+// Sequences can contain sequences, but not forever.
+pub const MAX_SEQUENCE_DEPTH: usize = 8;
+// Nobody needs a step to wait longer than ten minutes.
+pub const MAX_STEP_DELAY: u64 = 600_000;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CmdValue {
     // We support string responses - eg "sayhi" => "hi!"
     StringResponse(String),
-    // Aliases are automatically parsed and translated
+    // Aliases are automatically parsed and translated - eg "wb" => "game bet_for {args}"
     Alias(String),
     // Otherwise, it requires code implementation
     Generic(String),
+    // Or we run a list of other commands, in order
+    Sequence(Vec<SeqStep>),
+    // Overlays use this to switch off a command from a lower layer
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeqStep {
+    // Full command string, as it would be typed in chat (without the prefix)
+    pub command: String,
+    // Milliseconds to wait before running this step
+    #[serde(default = "get_zero_lol")]
+    pub delay: u64,
+}
+
+/* A sequence that is being run.
+ *
+ * Nested sequences are pushed on top and finish before the rest of the outer one.
+ * A step with a delay pauses the whole run, which is handed back to be picked up
+ * again later (see SeqNext::Wait), rather than blocking everything else meanwhile.
+ */
+#[derive(Debug, Clone)]
+pub struct SeqRun {
+    frames: Vec<VecDeque<SeqStep>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SeqNext {
+    // Run this command string next
+    Run(String),
+    // Wait this many milliseconds, then call advance again
+    Wait(u64),
+    Done,
+}
+
+impl SeqRun {
+    pub fn new(steps: Vec<SeqStep>) -> SeqRun {
+        SeqRun {
+            frames: vec![steps.into_iter().collect()],
+        }
+    }
+
+    // Returns false (and leaves the run alone) if it's nested too deeply.
+    pub fn push(&mut self, steps: Vec<SeqStep>) -> bool {
+        if self.frames.len() >= MAX_SEQUENCE_DEPTH {
+            return false;
+        }
+        self.frames.push(steps.into_iter().collect());
+        true
+    }
+
+    pub fn advance(&mut self) -> SeqNext {
+        while let Some(frame) = self.frames.last_mut() {
+            match frame.front_mut() {
+                Some(step) if step.delay > 0 => {
+                    // Only wait once, the step runs when we come back.
+                    let delay = step.delay.min(MAX_STEP_DELAY);
+                    step.delay = 0;
+                    return SeqNext::Wait(delay);
+                }
+                Some(_) => {
+                    return match frame.pop_front() {
+                        Some(step) => SeqNext::Run(step.command),
+                        None => SeqNext::Done,
+                    };
+                }
+                None => {
+                    self.frames.pop();
+                }
+            }
+        }
+        SeqNext::Done
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,6 +180,9 @@ pub struct CommandNode {
     pub value: CmdValue,
     #[serde(default = "get_false_lol")]
     pub admin_only: bool,
+    // Moderators and the broadcaster (and the admin) only
+    #[serde(default = "get_false_lol")]
+    pub mod_only: bool,
     #[serde(default = "HashMap::new")]
     pub subcommands: HashMap<String, CommandNode>,
     // Anything with admin marked as true is auto-hidden
@@ -64,6 +190,12 @@ pub struct CommandNode {
     pub hidden: bool,
     #[serde(default = "String::new")]
     pub sound: String,
+    // If false, the command also triggers on a plain message with no prefix
+    #[serde(default = "get_true_lol")]
+    pub require_prefix: bool,
+    // Which file this command was loaded from (for debugging layers)
+    #[serde(skip)]
+    pub layer: String,
 }
 
 impl CommandNode {
@@ -71,9 +203,12 @@ impl CommandNode {
         CommandNode {
             value,
             admin_only: false,
+            mod_only: false,
             subcommands: HashMap::new(),
             hidden: false,
             sound: String::new(),
+            require_prefix: true,
+            layer: String::new(),
         }
     }
 
@@ -81,9 +216,12 @@ impl CommandNode {
         CommandNode {
             value,
             admin_only: false,
+            mod_only: false,
             subcommands: HashMap::new(),
             hidden: true,
             sound: String::new(),
+            require_prefix: true,
+            layer: String::new(),
         }
     }
 
@@ -91,9 +229,24 @@ impl CommandNode {
         CommandNode {
             value,
             admin_only: true,
+            mod_only: false,
             subcommands: HashMap::new(),
             hidden: true,
             sound: String::new(),
+            require_prefix: true,
+            layer: String::new(),
+        }
+    }
+
+    // Whether someone may run this. Every step of a sequence is checked on its own.
+    pub fn allowed(&self, is_admin: bool, is_mod: bool) -> bool {
+        (is_admin || !self.admin_only) && (is_mod || !self.mod_only)
+    }
+
+    fn set_layer(&mut self, layer: &String) {
+        self.layer = layer.clone();
+        for (_, sc) in self.subcommands.iter_mut() {
+            sc.set_layer(layer);
         }
     }
 }
@@ -106,6 +259,10 @@ pub struct CommandTree {
     host: String,
     #[serde(default = "default_port")]
     port: String,
+    // Command prefixes, eg "!" or "bot " - unset means the defaults
+    prefixes: Option<Vec<String>>,
+    // Whether "@botname command" works as well - unset means yes
+    mentions: Option<bool>,
     #[serde(default = "HashMap::new")]
     commands: HashMap<String, CommandNode>,
 }
@@ -124,7 +281,7 @@ impl CommandTree {
                     return node;
                 }
                 let x: &[_] = &['-', '\n', '\r'];
-                let sc = String::from(*(&s.trim_matches(x)));
+                let sc = normalize(s.trim_matches(x));
                 match node.subcommands.get(&sc) {
                     Some(n) => {
                         let _ = itr.next();
@@ -143,32 +300,37 @@ impl CommandTree {
          * "wiw --start --timeout=20s functional!"
          * Therefore, we use itr as an iterator to the string,
          * which essentially returns sequential commands.
+         * On return, key holds whatever arguments are left over.
          */
+        self.find_recurse(key, HashSet::new())
+    }
+
+    pub fn find_recurse(
+        &self,
+        key: &mut String,
+        mut prev: HashSet<String>,
+    ) -> Option<&CommandNode> {
         let mut itr = key.as_str().split(' ').peekable();
         let cmd = match itr.next() {
-            Some(s) => s,
+            Some(s) => normalize(s),
             None => return None,
         };
-        /* after we're done, we do itr.collect::<Vec<String>>().join(' ') */
-        match self.commands.get(&cmd.to_lowercase()) {
+        match self.commands.get(&cmd) {
             Some(node) => match &node.value {
                 CmdValue::Alias(k) => {
-                    let mut h = HashSet::new();
-                    h.insert(key.clone());
-                    if let Some(res) = self.find_recurse(&k, h) {
-                        // we have a mapping - res
-                        // I've decided: No aliases for subcommands.
-                        // In theory they support it, but I refuse to actually allow it.
-                        let ret = Some(self.find_subcommands(&mut itr, res));
-                        *key = String::from(itr.collect::<Vec<&str>>().join(" "));
-                        ret
-                    } else {
-                        None
+                    // Each alias may only be expanded once, otherwise we'd loop forever.
+                    if !prev.insert(cmd) {
+                        return None;
                     }
+                    let args = itr.collect::<Vec<&str>>().join(" ");
+                    *key = CommandTree::expand_alias(k, &args);
+                    self.find_recurse(key, prev)
                 }
+                CmdValue::Disabled => None,
                 _ => {
                     let ret = Some(self.find_subcommands(&mut itr, node));
-                    *key = String::from(itr.into_iter().collect::<Vec<&str>>().join(" "));
+                    /* after we're done, we do itr.collect::<Vec<String>>().join(' ') */
+                    *key = String::from(itr.collect::<Vec<&str>>().join(" "));
                     ret
                 }
             },
@@ -176,34 +338,113 @@ impl CommandTree {
         }
     }
 
-    pub fn find_recurse(&self, key: &String, mut prev: HashSet<String>) -> Option<&CommandNode> {
-        match self.commands.get(key) {
-            Some(node) => match &node.value {
-                CmdValue::Alias(k) => {
-                    if prev.insert(k.clone()) {
-                        self.find_recurse(&k, prev)
-                    } else {
-                        None
-                    }
+    pub fn parse_command(&self, message: &String, nick: &String) -> Option<(String, bool)> {
+        /* Splits the prefix off a chat message.
+         * Returns the command string, and whether it was actually prefixed (or mentioned us).
+         * Unprefixed messages are still returned, see prefix_optional.
+         */
+        let message = message.trim();
+        let mut prefixes = match &self.prefixes {
+            Some(p) => p.clone(),
+            None => default_prefixes(),
+        };
+        // Longest first, so "!!" wins over "!"
+        prefixes.sort_by(|a, b| b.len().cmp(&a.len()));
+        let mut res = (message, false);
+        if self.mentions.unwrap_or(true) {
+            let mention = format!("@{}", nick);
+            let n = mention.len();
+            if message.is_char_boundary(n) && message[..n].eq_ignore_ascii_case(&mention) {
+                let rest = &message[n..];
+                if rest.is_empty()
+                    || rest.starts_with(|c: char| c.is_whitespace() || c == ',' || c == ':')
+                {
+                    res = (
+                        rest.trim_start_matches(|c: char| c == ',' || c == ':'),
+                        true,
+                    );
                 }
-                _ => Some(node),
-            },
-            None => None,
+            }
+        }
+        if !res.1 {
+            for prefix in prefixes.iter().filter(|p| !p.is_empty()) {
+                if message.starts_with(prefix.as_str()) {
+                    res = (&message[prefix.len()..], true);
+                    break;
+                }
+            }
+        }
+        let command = res.0.trim();
+        if command.is_empty() {
+            None
+        } else {
+            Some((command.to_string(), res.1))
         }
     }
 
-    pub fn validate(ct: &CommandTree) -> bool {
-        for (key, _value) in &ct.commands {
-            for c in key.chars() {
-                if c.is_uppercase() {
-                    return false;
+    pub fn prefix_optional(&self, command: &String) -> bool {
+        match self.find(&mut command.clone()) {
+            Some(node) => !node.require_prefix,
+            None => false,
+        }
+    }
+
+    // The command a chat message triggers, if it triggers one.
+    pub fn command_in(&self, message: &String, nick: &String) -> Option<String> {
+        match self.parse_command(message, nick) {
+            Some((command, true)) => Some(command),
+            // Some commands don't need a prefix at all.
+            Some((command, false)) if self.prefix_optional(&command) => Some(command),
+            _ => None,
+        }
+    }
+
+    // Whether a command string ends up at this built in command (eg. "mod:filtertest").
+    pub fn resolves_to(&self, command: &String, generic: &str) -> bool {
+        match self.find(&mut command.clone()).map(|n| &n.value) {
+            Some(CmdValue::Generic(x)) => x == generic,
+            _ => false,
+        }
+    }
+
+    // The layer that disabled a command, following aliases to what they point at.
+    pub fn disabled_by(&self, key: &String) -> Option<&String> {
+        let mut key = key.clone();
+        let mut prev = HashSet::new();
+        loop {
+            let cmd = normalize(key.split(' ').next().unwrap_or(""));
+            let node = self.commands.get(&cmd)?;
+            match &node.value {
+                CmdValue::Disabled => return Some(&node.layer),
+                CmdValue::Alias(k) if prev.insert(cmd) => {
+                    let args = key.split_once(' ').map_or("", |(_, a)| a).to_string();
+                    key = CommandTree::expand_alias(k, &args);
                 }
+                _ => return None,
             }
         }
-        true
     }
 
-    pub fn from_json_file(filename: &Path) -> CommandTree {
+    pub fn expand_alias(alias: &String, args: &String) -> String {
+        /* Aliases are a full command path plus optional pre-filled arguments:
+         * "so" => "shoutout {args}"
+         * "wb" => "game bet_for"
+         * Without a {args} placeholder, the caller's arguments are appended.
+         */
+        if alias.contains("{args}") {
+            alias.replace("{args}", args).trim().to_string()
+        } else if args.is_empty() {
+            alias.clone()
+        } else {
+            format!("{} {}", alias, args)
+        }
+    }
+
+    pub fn validate(ct: &CommandTree) -> bool {
+        keys_normalized(&ct.commands)
+    }
+
+    fn read_json_file(filename: &Path) -> serde_json::Value {
         let mut file =
             File::open(filename).expect(&format!("Could not open file: {}", filename.display()));
         let mut contents = String::new();
@@ -211,11 +452,52 @@ impl CommandTree {
             "Could not read file {} to string.",
             filename.display()
         ));
-        CommandTree::from_json(serde_json::from_str(&contents).unwrap())
+        serde_json::from_str(&contents).unwrap()
+    }
+
+    pub fn from_json_file(filename: &Path) -> CommandTree {
+        let mut ct = CommandTree::from_json(CommandTree::read_json_file(filename));
+        ct.set_layer(&filename.display().to_string());
+        ct
+    }
+
+    /* Layered trees - a shared base file, plus an optional overlay (usually per channel).
+     * The overlay can add commands, override them, or disable them with "value": "Disabled".
+     * Overlay entries replace base entries entirely, subcommands included.
+     */
+    pub fn from_layered_files(base: &Path, overlay: &Path) -> CommandTree {
+        let mut ct = CommandTree::from_json_file(base);
+        if overlay.exists() {
+            let mut top: CommandTree =
+                serde_json::from_value(CommandTree::read_json_file(overlay)).unwrap();
+            top.commands = normalize_keys(top.commands);
+            top.set_layer(&overlay.display().to_string());
+            ct.overlay(top);
+        }
+        ct
+    }
+
+    pub fn overlay(&mut self, top: CommandTree) {
+        if top.prefixes.is_some() {
+            self.prefixes = top.prefixes;
+        }
+        if top.mentions.is_some() {
+            self.mentions = top.mentions;
+        }
+        for (name, node) in top.commands {
+            self.commands.insert(name, node);
+        }
+    }
+
+    fn set_layer(&mut self, layer: &String) {
+        for (_, node) in self.commands.iter_mut() {
+            node.set_layer(layer);
+        }
     }
 
     pub fn from_json(json: serde_json::Value) -> CommandTree {
         let mut ct: CommandTree = serde_json::from_value(json).unwrap();
+        ct.commands = normalize_keys(ct.commands);
         ct.commands.insert(
             String::from("rb:cancel"),
             CommandNode::new_private(CmdValue::Generic(String::from("internal:cancel"))),
@@ -238,6 +520,8 @@ impl CommandTree {
                     version: default_ver(),
                     port: default_port(),
                     host: default_host(),
+                    prefixes: None,
+                    mentions: None,
                 };
                 ct.commands.insert("json".to_string(), 
                                    CommandNode::new_easter(
@@ -250,3 +534,317 @@ impl CommandTree {
         }
     }
 }
+
+#[cfg(test)]
+mod command_tree_tests {
+    use super::*;
+
+    fn test_tree() -> CommandTree {
+        CommandTree::from_json(serde_json::json!({
+            "commands": {
+                "Hello": { "value": { "StringResponse": "hi!" } },
+                "game": {
+                    "value": { "Generic": "game:status" },
+                    "subcommands": { "Bet_For": { "value": { "Generic": "game:bet_for" } } }
+                },
+                "wb": { "value": { "Alias": "GAME BET_FOR" } }
+            }
+        }))
+    }
+
+    fn generic(node: Option<&CommandNode>) -> String {
+        match node.map(|n| &n.value) {
+            Some(CmdValue::Generic(x)) => x.clone(),
+            Some(CmdValue::StringResponse(x)) => x.clone(),
+            _ => String::from("none"),
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("HeLLo"), "hello");
+        assert_eq!(normalize("ＨＥＬＬＯ"), "hello");
+        assert_eq!(normalize("Straße"), "strasse");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(CommandTree::validate(&test_tree()));
+        let raw: CommandTree = serde_json::from_value(
+            serde_json::json!({ "commands": { "Hello": { "value": "Disabled" } } }),
+        )
+        .unwrap();
+        assert!(!CommandTree::validate(&raw));
+    }
+
+    #[test]
+    fn test_collisions() {
+        for _ in 0..10 {
+            let ct = CommandTree::from_json(serde_json::json!({
+                "commands": {
+                    "hello": { "value": { "StringResponse": "lower" } },
+                    "HELLO": { "value": { "StringResponse": "upper" } },
+                    "Hello": { "value": { "StringResponse": "title" } }
+                }
+            }));
+            assert_eq!(generic(ct.find(&mut String::from("hello"))), "upper");
+        }
+    }
+
+    #[test]
+    fn test_mixed_case() {
+        let ct = test_tree();
+        assert_eq!(generic(ct.find(&mut String::from("HELLO"))), "hi!");
+        let mut key = String::from("Game BET_for 100");
+        assert_eq!(generic(ct.find(&mut key)), "game:bet_for");
+        assert_eq!(key, "100");
+    }
+
+    #[test]
+    fn test_full_width() {
+        let ct = test_tree();
+        assert_eq!(generic(ct.find(&mut String::from("ｈｅｌｌｏ"))), "hi!");
+        let mut key = String::from("ｇａｍｅ ｂｅｔ＿ｆｏｒ 5");
+        assert_eq!(generic(ct.find(&mut key)), "game:bet_for");
+        assert_eq!(key, "5");
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let mut ct = test_tree();
+        let nick = String::from("RustyBot");
+        let parse = |ct: &CommandTree, m: &str| ct.parse_command(&String::from(m), &nick);
+        assert_eq!(
+            parse(&ct, "!hello there"),
+            Some((String::from("hello there"), true))
+        );
+        assert_eq!(
+            parse(&ct, "bot  hello"),
+            Some((String::from("hello"), true))
+        );
+        assert_eq!(
+            parse(&ct, "@rustybot hello"),
+            Some((String::from("hello"), true))
+        );
+        assert_eq!(
+            parse(&ct, "@rustybotfan hello"),
+            Some((String::from("@rustybotfan hello"), false))
+        );
+        assert_eq!(parse(&ct, "hello"), Some((String::from("hello"), false)));
+        assert_eq!(parse(&ct, "!"), None);
+
+        ct.prefixes = Some(vec![String::from("?"), String::from("??")]);
+        ct.mentions = Some(false);
+        assert_eq!(parse(&ct, "??hello"), Some((String::from("hello"), true)));
+        assert_eq!(parse(&ct, "!hello"), Some((String::from("!hello"), false)));
+        assert_eq!(
+            parse(&ct, "@rustybot hello"),
+            Some((String::from("@rustybot hello"), false))
+        );
+    }
+
+    #[test]
+    fn test_prefix_optional() {
+        let mut ct = test_tree();
+        assert!(!ct.prefix_optional(&String::from("hello")));
+        ct.commands.get_mut("hello").unwrap().require_prefix = false;
+        assert!(ct.prefix_optional(&String::from("HELLO")));
+        assert!(!ct.prefix_optional(&String::from("goodbye")));
+    }
+
+    #[test]
+    fn test_resolves_to() {
+        let mut ct = CommandTree::from_json(serde_json::json!({
+            "commands": {
+                "filtertest": { "value": { "Generic": "mod:filtertest" } },
+                "ft": { "value": { "Alias": "filtertest {args}" } },
+                "hello": { "value": { "StringResponse": "hi!" } }
+            }
+        }));
+        let nick = String::from("rustybot");
+        let filtertest = |ct: &CommandTree, m: &str| match ct.command_in(&m.to_string(), &nick) {
+            Some(command) => ct.resolves_to(&command, "mod:filtertest"),
+            None => false,
+        };
+        assert!(filtertest(&ct, "!filtertest buy followers"));
+        assert!(filtertest(&ct, "@RustyBot ft buy followers"));
+        assert!(!filtertest(&ct, "filtertest buy followers"));
+        assert!(!filtertest(&ct, "!hello filtertest"));
+        assert!(!filtertest(&ct, "!nothing"));
+        ct.commands.get_mut("filtertest").unwrap().require_prefix = false;
+        assert!(filtertest(&ct, "filtertest buy followers"));
+    }
+
+    #[test]
+    fn test_alias_target() {
+        let ct = test_tree();
+        let mut key = String::from("WB 50");
+        assert_eq!(generic(ct.find(&mut key)), "game:bet_for");
+        assert_eq!(key, "50");
+    }
+
+    #[test]
+    fn test_sequence() {
+        let step = |command: &str, delay: u64| SeqStep {
+            command: command.to_string(),
+            delay,
+        };
+        let mut run = SeqRun::new(vec![
+            step("say one", 0),
+            step("inner", 0),
+            step("say four", 0),
+        ]);
+        assert_eq!(run.advance(), SeqNext::Run(String::from("say one")));
+        assert_eq!(run.advance(), SeqNext::Run(String::from("inner")));
+        // "inner" turned out to be a sequence, it runs before the rest of the outer one.
+        assert!(run.push(vec![step("say two", 1500), step("say three", 999999999)]));
+        assert_eq!(run.advance(), SeqNext::Wait(1500));
+        assert_eq!(run.advance(), SeqNext::Run(String::from("say two")));
+        assert_eq!(run.advance(), SeqNext::Wait(MAX_STEP_DELAY));
+        assert_eq!(run.advance(), SeqNext::Run(String::from("say three")));
+        assert_eq!(run.advance(), SeqNext::Run(String::from("say four")));
+        assert_eq!(run.advance(), SeqNext::Done);
+        assert_eq!(run.advance(), SeqNext::Done);
+
+        let mut run = SeqRun::new(Vec::new());
+        for _ in 1..MAX_SEQUENCE_DEPTH {
+            assert!(run.push(vec![step("loop", 0)]));
+        }
+        assert!(!run.push(vec![step("loop", 0)]));
+    }
+
+    #[test]
+    fn test_sequence_permissions() {
+        let ct = CommandTree::from_json(serde_json::json!({
+            "commands": {
+                "hype": { "value": { "Sequence": [
+                    { "command": "say let's go" },
+                    { "command": "game worked", "delay": 2000 }
+                ] } },
+                "say": { "value": { "Generic": "meta:say" } },
+                "game": {
+                    "value": { "Generic": "game:status" },
+                    "subcommands": {
+                        "worked": { "value": { "Generic": "game:worked" }, "mod_only": true }
+                    }
+                },
+                "stop": { "value": { "Generic": "meta:stop" }, "admin_only": true }
+            }
+        }));
+        let steps = match ct.find(&mut String::from("hype")).map(|n| &n.value) {
+            Some(CmdValue::Sequence(steps)) => steps.clone(),
+            _ => panic!("hype should be a sequence"),
+        };
+        assert_eq!(steps[0].delay, 0);
+        assert_eq!(steps[1].delay, 2000);
+        // Anyone can start it, but each step is checked for whoever started it.
+        let allowed = |command: &str, is_admin: bool, is_mod: bool| {
+            ct.find(&mut command.to_string())
+                .map(|n| n.allowed(is_admin, is_mod))
+        };
+        assert_eq!(allowed("hype", false, false), Some(true));
+        assert_eq!(allowed(&steps[0].command, false, false), Some(true));
+        assert_eq!(allowed(&steps[1].command, false, false), Some(false));
+        assert_eq!(allowed(&steps[1].command, false, true), Some(true));
+        assert_eq!(allowed("stop", false, true), Some(false));
+        assert_eq!(allowed("stop", true, true), Some(true));
+    }
+
+    #[test]
+    fn test_layers() {
+        let base = Path::new("test_base_commands.json");
+        let overlay = Path::new("test_overlay_commands.json");
+        let write = |path: &Path, json: serde_json::Value| {
+            serde_json::to_writer(&File::create(path).unwrap(), &json).unwrap()
+        };
+        write(
+            base,
+            serde_json::json!({
+                "commands": {
+                    "hello": { "value": { "StringResponse": "hi!" } },
+                    "lurk": { "value": { "StringResponse": "enjoy the lurk" } },
+                    "slots": { "value": { "Generic": "game:slots" } },
+                    "spin": { "value": { "Alias": "slots {args}" } }
+                }
+            }),
+        );
+        // No overlay file is fine, everything comes from the base.
+        let ct = CommandTree::from_layered_files(base, overlay);
+        let layer =
+            |ct: &CommandTree, key: &str| ct.find(&mut key.to_string()).map(|n| n.layer.clone());
+        assert_eq!(layer(&ct, "hello"), Some(base.display().to_string()));
+
+        write(
+            overlay,
+            serde_json::json!({
+                "prefixes": ["?"],
+                "commands": {
+                    "HELLO": { "value": { "StringResponse": "howdy!" } },
+                    "discord": { "value": { "StringResponse": "discord.gg/..." } },
+                    "Slots": { "value": "Disabled" }
+                }
+            }),
+        );
+        let ct = CommandTree::from_layered_files(base, overlay);
+        let base_layer = Some(base.display().to_string());
+        let overlay_layer = Some(overlay.display().to_string());
+        // Override
+        assert_eq!(generic(ct.find(&mut String::from("hello"))), "howdy!");
+        assert_eq!(layer(&ct, "hello"), overlay_layer);
+        // Add
+        assert_eq!(layer(&ct, "discord"), overlay_layer);
+        // Untouched
+        assert_eq!(layer(&ct, "lurk"), base_layer);
+        assert_eq!(
+            ct.parse_command(&String::from("?lurk"), &String::from("bot")),
+            Some((String::from("lurk"), true))
+        );
+        // Disable, which also takes out aliases to it
+        assert!(ct.find(&mut String::from("slots 50")).is_none());
+        assert!(ct.find(&mut String::from("spin 50")).is_none());
+        assert_eq!(
+            ct.disabled_by(&String::from("slots 50")),
+            overlay_layer.as_ref()
+        );
+        assert_eq!(
+            ct.disabled_by(&String::from("spin 50")),
+            overlay_layer.as_ref()
+        );
+        assert_eq!(ct.disabled_by(&String::from("lurk")), None);
+        assert_eq!(ct.disabled_by(&String::from("nothing")), None);
+
+        let _ = std::fs::remove_file(base);
+        let _ = std::fs::remove_file(overlay);
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let expand = |alias: &str, args: &str| {
+            CommandTree::expand_alias(&alias.to_string(), &args.to_string())
+        };
+        assert_eq!(expand("shoutout {args}", "@someone"), "shoutout @someone");
+        assert_eq!(expand("shoutout {args}", ""), "shoutout");
+        assert_eq!(expand("say {args} and {args}", "hi"), "say hi and hi");
+        assert_eq!(expand("game bet_for", "50"), "game bet_for 50");
+        assert_eq!(expand("game bet_for", ""), "game bet_for");
+    }
+
+    #[test]
+    fn test_alias_cycle() {
+        let ct = CommandTree::from_json(serde_json::json!({
+            "commands": {
+                "hello": { "value": { "StringResponse": "hi!" } },
+                "so": { "value": { "Alias": "say {args}!" } },
+                "say": { "value": { "Alias": "hello {args}" } },
+                "ping": { "value": { "Alias": "pong" } },
+                "pong": { "value": { "Alias": "PING" } },
+                "me": { "value": { "Alias": "me" } }
+            }
+        }));
+        let mut key = String::from("so there");
+        assert_eq!(generic(ct.find(&mut key)), "hi!");
+        assert_eq!(key, "there!");
+        assert!(ct.find(&mut String::from("ping")).is_none());
+        assert!(ct.find(&mut String::from("me too")).is_none());
+    }
+}
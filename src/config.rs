@@ -0,0 +1,26 @@
+use std::path::{Path, PathBuf};
+
+/* Per-channel configuration
+ *
+ * Shared files (commands.json and friends) live in the working directory.
+ * Anything specific to one channel lives in channels/<channel>/, for example:
+ *   channels/desktopfolder/commands.json
+ */
+
+pub fn channel_dir(channel: &String) -> PathBuf {
+    Path::new("channels").join(channel)
+}
+
+pub fn channel_file(channel: &String, filename: &str) -> PathBuf {
+    channel_dir(channel).join(filename)
+}
+
+// The channel's own copy of a file if it has one, otherwise the shared one.
+pub fn config_file(channel: &String, filename: &str) -> PathBuf {
+    let path = channel_file(channel, filename);
+    if path.exists() {
+        path
+    } else {
+        PathBuf::from(filename)
+    }
+}
@@ -0,0 +1,213 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/* Text cleanup before filtering.
+ *
+ * Spam bots dodge filters with text that looks normal but isn't: Cyrillic and Greek
+ * look-alikes ("bіgfollоws" with a Cyrillic і and о), full-width letters, zero-width
+ * characters between letters, accents or zalgo marks, and s p a c e d  o u t words.
+ * `clean` undoes all of that, so rules only have to be written for plain ASCII.
+ *
+ * Case is kept, so caps checks still work. The cleaned text is only for matching,
+ * logs and moderation entries keep what was actually sent.
+ */
+
+// Spaced out letters only get joined back up when there's at least this many in a row.
+const SPACED_RUN: usize = 3;
+
+// Characters that take up no space, or render as blank.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x00AD
+            | 0x034F
+            | 0x061C
+            | 0x115F
+            | 0x1160
+            | 0x17B4
+            | 0x17B5
+            | 0x180E
+            | 0x200B..=0x200F
+            | 0x202A..=0x202E
+            | 0x2060..=0x206F
+            | 0x3164
+            | 0xFE00..=0xFE0F
+            | 0xFEFF
+            | 0xFFA0
+            | 0xFFF9..=0xFFFB
+            | 0x1D173..=0x1D17A
+            | 0xE0000..=0xE0FFF
+    )
+}
+
+// Look-alikes that NFKC leaves alone, mapped to the ASCII letter they imitate.
+fn confusable(c: char) -> Option<char> {
+    let ascii = match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'b',
+        'е' | 'ё' => 'e',
+        'һ' => 'h',
+        'і' | 'ї' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'ӏ' => 'l',
+        'м' => 'm',
+        'н' => 'h',
+        'о' => 'o',
+        'р' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' => 's',
+        'т' => 't',
+        'ц' => 'u',
+        'ѵ' => 'v',
+        'ԝ' | 'ш' => 'w',
+        'х' => 'x',
+        'у' => 'y',
+        'ԁ' => 'd',
+        'А' => 'A',
+        'В' => 'B',
+        'С' => 'C',
+        'Е' | 'Ё' => 'E',
+        'Н' => 'H',
+        'І' | 'Ї' => 'I',
+        'Ј' => 'J',
+        'К' => 'K',
+        'М' => 'M',
+        'О' => 'O',
+        'Р' => 'P',
+        'Ѕ' => 'S',
+        'Т' => 'T',
+        'Х' => 'X',
+        'У' | 'Ү' => 'Y',
+        'Ԁ' => 'D',
+        'Ԛ' => 'Q',
+        'Ԝ' => 'W',
+        // Greek
+        'α' => 'a',
+        'β' => 'b',
+        'ε' => 'e',
+        'η' => 'n',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'χ' => 'x',
+        'γ' => 'y',
+        'ω' => 'w',
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        // Everything else
+        'ı' => 'i',
+        'ȷ' => 'j',
+        'ɑ' => 'a',
+        'ɡ' => 'g',
+        'ɩ' => 'i',
+        // Small capitals are lowercase letters, they shouldn't look like shouting
+        'ᴀ' => 'a',
+        'ʙ' => 'b',
+        'ᴄ' => 'c',
+        'ᴅ' => 'd',
+        'ᴇ' => 'e',
+        'ʜ' => 'h',
+        'ɪ' => 'i',
+        'ᴊ' => 'j',
+        'ᴋ' => 'k',
+        'ʟ' => 'l',
+        'ᴍ' => 'm',
+        'ɴ' => 'n',
+        'ᴏ' => 'o',
+        'ᴘ' => 'p',
+        'ʀ' => 'r',
+        'ᴛ' => 't',
+        'ᴜ' => 'u',
+        'ᴠ' => 'v',
+        'ᴡ' => 'w',
+        'ʏ' => 'y',
+        'ᴢ' => 'z',
+        _ => return None,
+    };
+    Some(ascii)
+}
+
+// "b i g f o l l o w s" => "bigfollows", but "I am a" stays as it is.
+fn join_spaced(words: Vec<String>) -> Vec<String> {
+    let mut joined = Vec::new();
+    let mut run: Vec<String> = Vec::new();
+    fn flush(run: &mut Vec<String>, joined: &mut Vec<String>) {
+        if run.len() >= SPACED_RUN {
+            joined.push(run.concat());
+            run.clear();
+        } else {
+            joined.append(run);
+        }
+    }
+    for word in words {
+        if word.chars().count() == 1 {
+            run.push(word);
+        } else {
+            flush(&mut run, &mut joined);
+            joined.push(word);
+        }
+    }
+    flush(&mut run, &mut joined);
+    joined
+}
+
+pub fn clean(text: &str) -> String {
+    // NFKD takes care of full-width & styled letters, and splits accents off to be dropped.
+    let plain: String = text
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c) && !is_invisible(*c))
+        .map(|c| confusable(c).unwrap_or(c))
+        .map(|c| if c == '\u{2800}' { ' ' } else { c })
+        .collect();
+    let words = plain
+        .nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    join_spaced(words).join(" ")
+}
+
+#[cfg(test)]
+mod confusables_tests {
+    use super::*;
+
+    #[test]
+    fn test_clean() {
+        assert_eq!(clean("hello there"), "hello there");
+        // Cyrillic і, о and а
+        assert_eq!(clean("bіgfollоws dot cоm"), "bigfollows dot com");
+        assert_eq!(clean("ｂｉｇｆｏｌｌｏｗｓ"), "bigfollows");
+        assert_eq!(clean("big\u{200B}fol\u{FEFF}lows"), "bigfollows");
+        assert_eq!(clean("b\u{0301}i\u{0302}g"), "big");
+        assert_eq!(clean("ВІG"), "BIG");
+        assert_eq!(clean("ʜᴇʟʟᴏ ᴛʜᴇʀᴇ"), "hello there");
+    }
+
+    #[test]
+    fn test_spacing() {
+        assert_eq!(clean("  lots   of\u{3000}space "), "lots of space");
+        assert_eq!(clean("buy b i g f o l l o w s now"), "buy bigfollows now");
+        assert_eq!(clean("I am a streamer"), "I am a streamer");
+        assert_eq!(clean("a b"), "a b");
+    }
+}
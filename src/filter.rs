@@ -0,0 +1,760 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::chat::{ChatMessage, Role};
+use crate::confusables::clean as clean_text;
+use crate::heuristics::{emote_count, symbol_ratio, zalgo_marks, History};
+use crate::ignore::IgnoreList;
+use crate::links::{find_links, is_allowed};
+use crate::raid::RaidConfig;
+use crate::strikes::StrikeConfig;
+
+/* Chat filter - an ordered list of rules, usually loaded from filters.json.
+ *
+ * The first rule that matches a message decides what happens to it.
+ * The file looks like:
+ *
+ *  {
+ *      "rules": [
+ *          {
+ *              "name": "bigfollows",
+ *              "check": { "Regex": "follower.{0,15}prime.{0,15}view" },
+ *              "action": "Ban",
+ *              "exempt": ["moderator", "vip"],
+ *              "reason": "Spam from {user} is not welcome in {channel}."
+ *          },
+ *          { "check": { "Caps": { "ratio": 0.8, "min_length": 12 } }, "action": { "Timeout": 60 } }
+ *      ]
+ *  }
+ *
+ * Ban results don't ban right away, they go through the strike ledger (see strikes.rs),
+ * which is configured by an optional "strikes" section next to "rules".
+ * Raid detection (see raid.rs) is configured by the "raid" section.
+ *
+ * Rules match against the message after confusables::clean, so look-alike letters,
+ * invisible characters and odd spacing don't get spam past them.
+ *
+ * Rules with "shadow": true never act, they only log what they would have done,
+ * so new rules can be tried out safely.
+ *
+ * Users on the ignore list (see ignore.rs) are skipped before any rule runs.
+ *
+ * Link rules skip anyone holding a permit (see permit), and the permit is used up
+ * by the first link they post.
+ *
+ * The file is checked for changes before every message, so edits apply without a restart.
+ * If it can't be loaded, whatever rules we had before stay in place.
+ */
+
+fn get_false_lol() -> bool {
+    false
+}
+fn default_reason() -> String {
+    "Your message has been marked as spam. To be unbanned, send a private message to {channel}."
+        .to_string()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterResult {
+    // Drop the message without doing anything
+    Skip,
+    Warn(String),
+    Delete(String),
+    Timeout(u64, String),
+    Ban(String),
+    // Only from moderators, rules can't unban
+    Unban(String),
+    Empty,
+}
+
+// What the filter decided about a message.
+#[derive(Debug)]
+pub struct Verdict {
+    // The rule that decided, empty if none did
+    pub rule: String,
+    pub result: FilterResult,
+    // Shadow rules that matched first, and what they would have done
+    pub shadow: Vec<(String, FilterResult)>,
+}
+
+impl Default for Verdict {
+    fn default() -> Verdict {
+        Verdict::new()
+    }
+}
+
+impl Verdict {
+    // Nothing matched.
+    pub fn new() -> Verdict {
+        Verdict {
+            rule: String::new(),
+            result: FilterResult::Empty,
+            shadow: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Check {
+    Regex(String),
+    // Whole words, case insensitive
+    Words(Vec<String>),
+    // Any link (obfuscated ones too) to a domain not on the allow list
+    Link {
+        #[serde(default = "Vec::new")]
+        allow: Vec<String>,
+    },
+    // Longer than this many characters
+    Length(usize),
+    // At least min_length letters, and this fraction of them in capitals
+    Caps {
+        ratio: f64,
+        min_length: usize,
+    },
+    // At least min_length visible characters, and this fraction of them symbols
+    Symbols {
+        ratio: f64,
+        min_length: usize,
+    },
+    // More than this many combining marks (zalgo text)
+    Zalgo(usize),
+    // More than this many emotes
+    Emotes(usize),
+    // The same user sending the same message `count` times in `window` seconds
+    Repeat {
+        count: usize,
+        window: u64,
+    },
+    // `users` different users sending the same message in `window` seconds (copy-paste waves)
+    Wave {
+        users: usize,
+        window: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Action {
+    // Stop checking rules and leave the message alone, eg. to let commands past later rules
+    Ignore,
+    Warn,
+    Delete,
+    Timeout(u64),
+    Ban,
+}
+
+impl Action {
+    pub fn result(&self, reason: String) -> FilterResult {
+        match self {
+            Action::Ignore => FilterResult::Empty,
+            Action::Warn => FilterResult::Warn(reason),
+            Action::Delete => FilterResult::Delete(reason),
+            Action::Timeout(secs) => FilterResult::Timeout(*secs, reason),
+            Action::Ban => FilterResult::Ban(reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(default = "String::new")]
+    pub name: String,
+    pub check: Check,
+    pub action: Action,
+    #[serde(default = "Vec::new")]
+    pub exempt: Vec<Role>,
+    // {user}, {channel} and {rule} are filled in
+    #[serde(default = "default_reason")]
+    pub reason: String,
+    // Log what would have happened, but don't do it
+    #[serde(default = "get_false_lol")]
+    pub shadow: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FilterFile {
+    #[serde(default = "Vec::new")]
+    rules: Vec<Rule>,
+    #[serde(default = "StrikeConfig::new")]
+    strikes: StrikeConfig,
+    #[serde(default = "RaidConfig::new")]
+    raid: RaidConfig,
+}
+
+impl Rule {
+    pub fn new(name: &str, check: Check, action: Action) -> Rule {
+        Rule {
+            name: name.to_string(),
+            check,
+            action,
+            exempt: Vec::new(),
+            reason: default_reason(),
+            shadow: false,
+        }
+    }
+
+    pub fn reason_for(&self, msg: &ChatMessage) -> String {
+        self.reason
+            .replace("{user}", &msg.name)
+            .replace("{channel}", &msg.channel)
+            .replace("{rule}", &self.name)
+    }
+
+    pub fn result_for(&self, msg: &ChatMessage) -> FilterResult {
+        self.action.result(self.reason_for(msg))
+    }
+}
+
+pub fn default_rules() -> Vec<Rule> {
+    vec![Rule::new(
+        "bigfollows",
+        Check::Regex(String::from(
+            r"follower.{0,15}prime.{0,15}view.{0,25}bigfollows.{0,10}com",
+        )),
+        Action::Ban,
+    )]
+}
+
+// The message as the rules see it, see confusables.rs.
+fn cleaned(msg: &ChatMessage) -> ChatMessage {
+    let mut clean = msg.clone();
+    clean.text = clean_text(&msg.text);
+    clean
+}
+
+struct CompiledRule {
+    rule: Rule,
+    re: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn compile(rule: Rule) -> Result<CompiledRule, String> {
+        let pattern = match &rule.check {
+            Check::Regex(r) => Some(r.clone()),
+            Check::Words(words) if !words.is_empty() => Some(format!(
+                r"(?i)(?:^|\W)(?:{})(?:$|\W)",
+                words
+                    .iter()
+                    .map(|w| regex::escape(w))
+                    .collect::<Vec<String>>()
+                    .join("|")
+            )),
+            _ => None,
+        };
+        let re = match pattern {
+            Some(p) => match Regex::new(&p) {
+                Ok(re) => Some(re),
+                Err(e) => return Err(format!("Rule '{}' has a bad pattern: {}", rule.name, e)),
+            },
+            None => None,
+        };
+        Ok(CompiledRule { rule, re })
+    }
+
+    // `clean` is the message after confusables::clean, which is what most checks look at.
+    // Zalgo & emotes need the message as it was sent.
+    fn matches(&self, msg: &ChatMessage, clean: &ChatMessage, history: &History, now: u64) -> bool {
+        let text = clean.text.as_str();
+        match &self.rule.check {
+            Check::Link { allow } => find_links(text).iter().any(|d| !is_allowed(d, allow)),
+            Check::Regex(_) | Check::Words(_) => match &self.re {
+                Some(re) => re.is_match(text),
+                None => false,
+            },
+            Check::Length(max) => text.chars().count() > *max,
+            Check::Caps { ratio, min_length } => {
+                let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+                let caps = text.chars().filter(|c| c.is_uppercase()).count();
+                letters > 0 && letters >= *min_length && caps as f64 / letters as f64 >= *ratio
+            }
+            Check::Symbols { ratio, min_length } => {
+                text.chars().filter(|c| !c.is_whitespace()).count() >= *min_length
+                    && symbol_ratio(text) >= *ratio
+            }
+            Check::Zalgo(max) => zalgo_marks(&msg.text) > *max,
+            Check::Emotes(max) => emote_count(msg) > *max,
+            Check::Repeat { count, window } => history.repeats(clean, *window, now) >= *count,
+            Check::Wave { users, window } => history.wave(clean, *window, now) >= *users,
+        }
+    }
+}
+
+pub struct FilterEngine {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    rules: Vec<CompiledRule>,
+    strikes: StrikeConfig,
+    raid: RaidConfig,
+    // User => unix time their link permit runs out
+    permits: HashMap<String, u64>,
+    history: History,
+    ignore: IgnoreList,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read_file(path: &Path) -> Result<FilterFile, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let file: FilterFile = serde_json::from_str(&contents)
+        .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+    Ok(file)
+}
+
+impl FilterEngine {
+    pub fn from_rules(rules: Vec<Rule>) -> Result<FilterEngine, String> {
+        let mut compiled = Vec::new();
+        for rule in rules {
+            compiled.push(CompiledRule::compile(rule)?);
+        }
+        Ok(FilterEngine {
+            path: None,
+            modified: None,
+            rules: compiled,
+            strikes: StrikeConfig::new(),
+            raid: RaidConfig::new(),
+            permits: HashMap::new(),
+            history: History::new(),
+            ignore: IgnoreList::new(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> FilterEngine {
+        let mut engine = FilterEngine::from_rules(default_rules()).unwrap();
+        engine.path = Some(path.to_path_buf());
+        if path.exists() {
+            engine.reload();
+        } else {
+            println!("No filter file at {}, using default rules.", path.display());
+        }
+        engine
+    }
+
+    pub fn reload_from(&mut self, path: &Path) -> bool {
+        self.path = Some(path.to_path_buf());
+        self.reload()
+    }
+
+    pub fn reload(&mut self) -> bool {
+        let path = match &self.path {
+            Some(p) => p.clone(),
+            None => return false,
+        };
+        self.modified = modified_time(&path);
+        let loaded = read_file(&path).and_then(|file| {
+            let mut engine = FilterEngine::from_rules(file.rules)?;
+            engine.strikes = file.strikes;
+            engine.raid = file.raid;
+            Ok(engine)
+        });
+        match loaded {
+            Ok(engine) => {
+                println!(
+                    "Loaded {} filter rule(s) from {}.",
+                    engine.rules.len(),
+                    path.display()
+                );
+                self.rules = engine.rules;
+                self.strikes = engine.strikes;
+                self.raid = engine.raid;
+                true
+            }
+            Err(e) => {
+                println!("[ERROR] Keeping previous filter rules. {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn reload_if_changed(&mut self) -> bool {
+        match &self.path {
+            Some(p) if modified_time(p) != self.modified => self.reload(),
+            _ => false,
+        }
+    }
+
+    pub fn strike_config(&self) -> &StrikeConfig {
+        &self.strikes
+    }
+
+    pub fn raid_config(&self) -> &RaidConfig {
+        &self.raid
+    }
+
+    pub fn set_ignore_list(&mut self, ignore: IgnoreList) {
+        self.ignore = ignore;
+    }
+
+    pub fn ignore_list(&mut self) -> &mut IgnoreList {
+        &mut self.ignore
+    }
+
+    // Lets the user post one link in the next `secs` seconds.
+    pub fn permit(&mut self, user: &String, secs: u64, now: u64) {
+        self.permits.insert(user.clone(), now + secs);
+    }
+
+    fn has_permit(&self, user: &String, now: u64) -> bool {
+        match self.permits.get(user) {
+            Some(until) => *until >= now,
+            None => false,
+        }
+    }
+
+    fn matching_rules(&self, msg: &ChatMessage, now: u64) -> Vec<&Rule> {
+        let clean = cleaned(msg);
+        let permitted = self.has_permit(&msg.name, now);
+        self.rules
+            .iter()
+            .filter(|r| !msg.has_any_role(&r.rule.exempt))
+            .filter(|r| !(permitted && matches!(r.rule.check, Check::Link { .. })))
+            .filter(|r| r.matches(msg, &clean, &self.history, now))
+            .map(|r| &r.rule)
+            .collect()
+    }
+
+    pub fn matching_rule(&self, msg: &ChatMessage, now: u64) -> Option<&Rule> {
+        self.matching_rules(msg, now)
+            .into_iter()
+            .find(|r| !r.shadow)
+    }
+
+    // Runs a message through the rules without remembering it or using up permits.
+    pub fn dry_run(&self, msg: &ChatMessage, now: u64) -> Verdict {
+        let mut verdict = Verdict::new();
+        if self.ignore.contains(&msg.name) {
+            verdict.result = FilterResult::Skip;
+            return verdict;
+        }
+        for rule in self.matching_rules(msg, now) {
+            if rule.shadow {
+                verdict
+                    .shadow
+                    .push((rule.name.clone(), rule.result_for(msg)));
+            } else {
+                verdict.rule = rule.name.clone();
+                verdict.result = rule.result_for(msg);
+                break;
+            }
+        }
+        verdict
+    }
+
+    pub fn check(&mut self, msg: &ChatMessage, now: u64) -> FilterResult {
+        self.judge(msg, now).result
+    }
+
+    pub fn judge(&mut self, msg: &ChatMessage, now: u64) -> Verdict {
+        let res = self.dry_run(msg, now);
+        if res.result == FilterResult::Skip {
+            return res;
+        }
+        self.history.record(&cleaned(msg), now);
+        if self.permits.contains_key(&msg.name) {
+            if !self.has_permit(&msg.name, now) {
+                self.permits.remove(&msg.name);
+            } else if !find_links(&msg.text).is_empty() {
+                println!("{} used their link permit.", msg.name);
+                self.permits.remove(&msg.name);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+
+    fn msg(text: &str) -> ChatMessage {
+        ChatMessage::new(
+            &String::from("spammer"),
+            &String::from("desktopfolder"),
+            &String::from(text),
+        )
+    }
+
+    fn engine() -> FilterEngine {
+        let mut words = Rule::new(
+            "words",
+            Check::Words(vec![String::from("badword"), String::from("c++")]),
+            Action::Delete,
+        );
+        words.reason = String::from("{user} said a bad word ({rule})");
+        let mut link = Rule::new(
+            "link",
+            Check::Link {
+                allow: vec![String::from("twitch.tv")],
+            },
+            Action::Timeout(10),
+        );
+        link.exempt = vec![Role::Vip];
+        let mut rules = default_rules();
+        rules.push(words);
+        rules.push(link);
+        rules.push(Rule::new("length", Check::Length(20), Action::Warn));
+        rules.push(Rule::new(
+            "caps",
+            Check::Caps {
+                ratio: 0.7,
+                min_length: 5,
+            },
+            Action::Ignore,
+        ));
+        FilterEngine::from_rules(rules).unwrap()
+    }
+
+    #[test]
+    fn test_default_rule() {
+        let mut e = engine();
+        match e.check(
+            &msg("cheap followers, primes and viewers on bigfollows . com"),
+            0,
+        ) {
+            FilterResult::Ban(reason) => assert!(reason.contains("desktopfolder")),
+            _ => panic!("Expected a ban."),
+        }
+        assert_eq!(e.check(&msg("hello"), 0), FilterResult::Empty);
+    }
+
+    #[test]
+    fn test_confusables() {
+        let mut e = engine();
+        // Cyrillic а, о and е, a zero width space, and full-width letters.
+        assert!(matches!(
+            e.check(&msg("bаd\u{200B}wоrd"), 0),
+            FilterResult::Delete(_)
+        ));
+        assert!(matches!(
+            e.check(&msg("ｂａｄｗｏｒｄ"), 0),
+            FilterResult::Delete(_)
+        ));
+        assert!(matches!(
+            e.check(&msg("b a d w o r d"), 0),
+            FilterResult::Delete(_)
+        ));
+        assert!(matches!(
+            e.check(
+                &msg("cheap followеrs, primеs and viewers on bigfollows dоt com"),
+                0
+            ),
+            FilterResult::Ban(_)
+        ));
+    }
+
+    #[test]
+    fn test_checks() {
+        let mut e = engine();
+        assert_eq!(
+            e.check(&msg("that's a BADWORD"), 0),
+            FilterResult::Delete(String::from("spammer said a bad word (words)"))
+        );
+        assert!(matches!(
+            e.check(&msg("I like C++!"), 0),
+            FilterResult::Delete(_)
+        ));
+        assert_eq!(e.check(&msg("badwords"), 0), FilterResult::Empty);
+        assert!(matches!(
+            e.check(&msg("see www.example.com"), 0),
+            FilterResult::Timeout(10, _)
+        ));
+        assert!(matches!(
+            e.check(&msg("free stuff at spam.xyz"), 0),
+            FilterResult::Timeout(10, _)
+        ));
+        assert!(matches!(
+            e.check(&msg("this message is definitely too long"), 0),
+            FilterResult::Warn(_)
+        ));
+        assert_eq!(e.check(&msg("STOP IT"), 0), FilterResult::Empty);
+        assert_eq!(e.check(&msg("OK"), 0), FilterResult::Empty);
+    }
+
+    #[test]
+    fn test_order_and_exemptions() {
+        let mut e = engine();
+        // Matches both the word list and the link rule, the first one wins.
+        assert!(matches!(
+            e.check(&msg("badword.com"), 0),
+            FilterResult::Delete(_)
+        ));
+        let mut vip = msg("see www.example.com");
+        vip.tags
+            .insert(String::from("badges"), String::from("vip/1"));
+        assert_eq!(e.check(&vip, 0), FilterResult::Empty);
+    }
+
+    #[test]
+    fn test_links() {
+        let mut e = engine();
+        assert!(matches!(
+            e.check(&msg("spam dot xyz"), 0),
+            FilterResult::Timeout(10, _)
+        ));
+        assert_eq!(e.check(&msg("clips.twitch.tv/x"), 0), FilterResult::Empty);
+
+        // A permit is good for one link, within the window.
+        e.permit(&String::from("spammer"), 60, 100);
+        assert_eq!(e.check(&msg("no link yet"), 110), FilterResult::Empty);
+        assert_eq!(
+            e.check(&msg("see www.example.com"), 120),
+            FilterResult::Empty
+        );
+        assert!(matches!(
+            e.check(&msg("see www.example.com"), 130),
+            FilterResult::Timeout(10, _)
+        ));
+        e.permit(&String::from("spammer"), 60, 100);
+        assert!(matches!(
+            e.check(&msg("see www.example.com"), 200),
+            FilterResult::Timeout(10, _)
+        ));
+    }
+
+    #[test]
+    fn test_heuristics() {
+        let rules = vec![
+            Rule::new("zalgo", Check::Zalgo(5), Action::Delete),
+            Rule::new(
+                "symbols",
+                Check::Symbols {
+                    ratio: 0.5,
+                    min_length: 6,
+                },
+                Action::Delete,
+            ),
+            Rule::new("emotes", Check::Emotes(4), Action::Delete),
+            Rule::new(
+                "repeat",
+                Check::Repeat {
+                    count: 3,
+                    window: 60,
+                },
+                Action::Timeout(60),
+            ),
+            Rule::new(
+                "wave",
+                Check::Wave {
+                    users: 3,
+                    window: 10,
+                },
+                Action::Ban,
+            ),
+        ];
+        let mut e = FilterEngine::from_rules(rules).unwrap();
+        assert_eq!(
+            e.check(
+                &msg("h\u{0301}\u{0302}\u{0303}\u{0304}\u{0305}\u{0306}i"),
+                0
+            ),
+            FilterResult::Delete(default_reason().replace("{channel}", "desktopfolder"))
+        );
+        assert!(matches!(
+            e.check(&msg("$$$ !!! ###"), 0),
+            FilterResult::Delete(_)
+        ));
+        assert_eq!(e.check(&msg(":)"), 0), FilterResult::Empty);
+        assert!(matches!(
+            e.check(&msg("LUL LUL LUL LUL LUL"), 0),
+            FilterResult::Delete(_)
+        ));
+
+        assert_eq!(e.check(&msg("first!"), 1), FilterResult::Empty);
+        assert_eq!(e.check(&msg("first!"), 2), FilterResult::Empty);
+        assert!(matches!(
+            e.check(&msg("first!"), 3),
+            FilterResult::Timeout(60, _)
+        ));
+
+        let mut other = msg("copy pasta");
+        assert_eq!(e.check(&other, 100), FilterResult::Empty);
+        other.name = String::from("second");
+        assert_eq!(e.check(&other, 101), FilterResult::Empty);
+        other.name = String::from("third");
+        assert!(matches!(e.check(&other, 102), FilterResult::Ban(_)));
+    }
+
+    #[test]
+    fn test_shadow() {
+        let mut shadow = Rule::new(
+            "new words",
+            Check::Words(vec![String::from("hello")]),
+            Action::Ban,
+        );
+        shadow.shadow = true;
+        let rules = vec![
+            shadow,
+            Rule::new(
+                "old words",
+                Check::Words(vec![String::from("hello")]),
+                Action::Warn,
+            ),
+        ];
+        let mut e = FilterEngine::from_rules(rules).unwrap();
+        let verdict = e.judge(&msg("hello there"), 0);
+        assert_eq!(verdict.rule, "old words");
+        assert!(matches!(verdict.result, FilterResult::Warn(_)));
+        assert_eq!(verdict.shadow.len(), 1);
+        assert_eq!(verdict.shadow[0].0, "new words");
+        assert!(matches!(verdict.shadow[0].1, FilterResult::Ban(_)));
+        assert!(e.dry_run(&msg("bye"), 0).shadow.is_empty());
+    }
+
+    #[test]
+    fn test_ignore_rule() {
+        let mut shadow = Rule::new("shadow length", Check::Length(10), Action::Ban);
+        shadow.shadow = true;
+        let rules = vec![
+            shadow,
+            Rule::new(
+                "clips",
+                Check::Regex(String::from("^!clip")),
+                Action::Ignore,
+            ),
+            Rule::new("length", Check::Length(10), Action::Delete),
+        ];
+        let mut e = FilterEngine::from_rules(rules).unwrap();
+        // The ignore rule stops the length rule, but the message still goes through.
+        let verdict = e.judge(&msg("!clip that was amazing"), 0);
+        assert_eq!(verdict.rule, "clips");
+        assert_eq!(verdict.result, FilterResult::Empty);
+        assert_eq!(verdict.shadow.len(), 1);
+        assert!(matches!(
+            e.check(&msg("that was amazing"), 0),
+            FilterResult::Delete(_)
+        ));
+    }
+
+    #[test]
+    fn test_ignore() {
+        let mut e = engine();
+        let mut ignore = IgnoreList::new();
+        ignore.set_nick(&String::from("rustybot"));
+        ignore.add(&String::from("spammer"));
+        e.set_ignore_list(ignore);
+        assert_eq!(e.check(&msg("badword"), 0), FilterResult::Skip);
+        let mut bot = msg("hello");
+        bot.name = String::from("RustyBot");
+        assert_eq!(e.check(&bot, 0), FilterResult::Skip);
+        e.ignore_list().remove(&String::from("spammer"));
+        assert!(matches!(
+            e.check(&msg("badword"), 0),
+            FilterResult::Delete(_)
+        ));
+    }
+
+    #[test]
+    fn test_bad_rule() {
+        let rules = vec![Rule::new(
+            "broken",
+            Check::Regex(String::from("(")),
+            Action::Ban,
+        )];
+        assert!(FilterEngine::from_rules(rules).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 
+use crate::accrual::AccrualConfig;
+use crate::amount::parse_amount;
+use crate::casino::{color, roulette_payout, slots_payout, Casino, CasinoConfig, RouletteBet};
 use crate::player_data::*;
+use crate::round::{Round, FAILS, WORKS};
 
 use lazy_static::lazy_static;
 
@@ -12,10 +17,405 @@ lazy_static! {
     static ref GAME_DUMP_PATH: &'static Path = Path::new("gamedump.json");
 }
 
+/* Game configuration, from game.json (or channels/<channel>/game.json).
+ *
+ *  { "payout": "Parimutuel", "house_cut": 0.05 }
+ *
+ * Fixed pays winners twice their stake, no matter how many people bet either way.
+ * Parimutuel splits the losing side's points between the winners, in proportion
+ * to their stakes, after the house takes its cut (0 to 1) of the losing side.
+ *
+ * Players need "min_games" played to show up on the winrate leaderboard,
+ * so one lucky bet doesn't put anyone at 100%.
+ *
+ * Passive points (see accrual.rs) are configured by the "accrual" section.
+ *
+ * Players can give each other points. "transfer_fee" (0 to 1) of every transfer
+ * disappears on the way, and "transfer_limit" caps how much anyone can give away
+ * in a day (0 for no limit).
+ *
+ * Slots and roulette (see casino.rs) are configured by the "casino" section.
+ *
+ * The "daily" section sets up the daily bonus and the bankruptcy floor:
+ *
+ *  "daily": { "amount": 100, "streak_bonus": 20, "max_streak": 7, "period": 86400,
+ *             "floor": 100, "bailout_period": 86400 }
+ *
+ * Claiming again within two periods keeps the streak going, and each day of streak
+ * (up to max_streak) adds streak_bonus. Anyone with less than "floor" points and no
+ * bet running can reset to the floor, once per bailout_period.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PayoutMode {
+    Fixed,
+    Parimutuel,
+}
+
+fn default_payout() -> PayoutMode {
+    PayoutMode::Fixed
+}
+fn default_house_cut() -> f64 {
+    0.0
+}
+fn default_min_games() -> i64 {
+    10
+}
+fn default_leaderboard_size() -> usize {
+    5
+}
+fn default_transfer_limit() -> i64 {
+    0
+}
+fn default_max_wager() -> i64 {
+    0
+}
+
+const MIN_WAGER: i64 = 5;
+
+fn default_daily_amount() -> i64 {
+    100
+}
+fn default_streak_bonus() -> i64 {
+    20
+}
+fn default_max_streak() -> i64 {
+    7
+}
+fn default_period() -> u64 {
+    DAY
+}
+fn default_floor() -> i64 {
+    100
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyConfig {
+    #[serde(default = "default_daily_amount")]
+    pub amount: i64,
+    #[serde(default = "default_streak_bonus")]
+    pub streak_bonus: i64,
+    #[serde(default = "default_max_streak")]
+    pub max_streak: i64,
+    #[serde(default = "default_period")]
+    pub period: u64,
+    #[serde(default = "default_floor")]
+    pub floor: i64,
+    #[serde(default = "default_period")]
+    pub bailout_period: u64,
+}
+
+impl Default for DailyConfig {
+    fn default() -> DailyConfig {
+        DailyConfig::new()
+    }
+}
+
+impl DailyConfig {
+    pub fn new() -> DailyConfig {
+        DailyConfig {
+            amount: default_daily_amount(),
+            streak_bonus: default_streak_bonus(),
+            max_streak: default_max_streak(),
+            period: default_period(),
+            floor: default_floor(),
+            bailout_period: default_period(),
+        }
+    }
+}
+
+// "5h 12m", for how long until something can be done again.
+fn wait(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s => format!("{}h {}m", s / (60 * 60), s / 60 % 60),
+    }
+}
+
+// Gives the player their daily bonus, returning how much it was.
+pub fn claim_daily(player: &mut Player, config: &DailyConfig, now: u64) -> Result<i64, String> {
+    if player.last_daily != 0 && now < player.last_daily + config.period {
+        return Err(format!(
+            "{} already claimed their bonus, come back in {}!",
+            player.name,
+            wait(player.last_daily + config.period - now)
+        ));
+    }
+    if player.last_daily != 0 && now < player.last_daily + 2 * config.period {
+        player.daily_streak += 1;
+    } else {
+        player.daily_streak = 1;
+    }
+    let bonus = config.amount
+        + config.streak_bonus * (player.daily_streak.min(config.max_streak.max(1)) - 1);
+    player.cash += bonus;
+    player.last_daily = now;
+    Ok(bonus)
+}
+
+// Resets a broke player to the floor.
+pub fn bail_out(player: &mut Player, config: &DailyConfig, now: u64) -> Result<(), String> {
+    if player.cash >= config.floor {
+        return Err(format!(
+            "{} isn't broke! (Bailouts are for players with less than {} points.)",
+            player.name, config.floor
+        ));
+    }
+    if player.last_bailout != 0 && now < player.last_bailout + config.bailout_period {
+        return Err(format!(
+            "{} was bailed out recently, try again in {}.",
+            player.name,
+            wait(player.last_bailout + config.bailout_period - now)
+        ));
+    }
+    player.cash = config.floor;
+    player.last_bailout = now;
+    Ok(())
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+// Twitch cuts chat messages off at 500 characters.
+const MAX_MESSAGE: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameConfig {
+    #[serde(default = "default_payout")]
+    pub payout: PayoutMode,
+    #[serde(default = "default_house_cut")]
+    pub house_cut: f64,
+    #[serde(default = "default_min_games")]
+    pub min_games: i64,
+    #[serde(default = "default_leaderboard_size")]
+    pub leaderboard_size: usize,
+    #[serde(default = "AccrualConfig::new")]
+    pub accrual: AccrualConfig,
+    #[serde(default = "default_house_cut")]
+    pub transfer_fee: f64,
+    #[serde(default = "default_transfer_limit")]
+    pub transfer_limit: i64,
+    // The most anyone can bet at once, 0 for no limit
+    #[serde(default = "default_max_wager")]
+    pub max_wager: i64,
+    #[serde(default = "CasinoConfig::new")]
+    pub casino: CasinoConfig,
+    #[serde(default = "DailyConfig::new")]
+    pub daily: DailyConfig,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig::new()
+    }
+}
+
+impl GameConfig {
+    pub fn new() -> GameConfig {
+        GameConfig {
+            payout: default_payout(),
+            house_cut: default_house_cut(),
+            min_games: default_min_games(),
+            leaderboard_size: default_leaderboard_size(),
+            accrual: AccrualConfig::new(),
+            transfer_fee: 0.0,
+            transfer_limit: default_transfer_limit(),
+            max_wager: default_max_wager(),
+            casino: CasinoConfig::new(),
+            daily: DailyConfig::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> GameConfig {
+        if !path.exists() {
+            return GameConfig::new();
+        }
+        let mut contents = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => match serde_json::from_str(&contents) {
+                Ok(config) => return config,
+                Err(e) => println!("[ERROR] Could not parse game config: {}", e),
+            },
+            Err(e) => println!("[ERROR] Could not read game config: {}", e),
+        }
+        GameConfig::new()
+    }
+
+    fn cut(&self) -> f64 {
+        self.house_cut.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ranking {
+    Cash,
+    Winrate,
+    Wins,
+    Streak,
+}
+
+impl Ranking {
+    pub fn parse(name: &str) -> Option<Ranking> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "cash" | "points" => Some(Ranking::Cash),
+            "winrate" => Some(Ranking::Winrate),
+            "wins" => Some(Ranking::Wins),
+            "streak" | "streaks" => Some(Ranking::Streak),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Ranking::Cash => "points",
+            Ranking::Winrate => "winrate",
+            Ranking::Wins => "wins",
+            Ranking::Streak => "best streak",
+        }
+    }
+
+    fn show(&self, p: &Player) -> String {
+        match self {
+            Ranking::Cash => format!("{}", p.cash),
+            Ranking::Winrate => format!("{:.1}%", winrate(p) * 100.0),
+            Ranking::Wins => format!("{}", p.wins),
+            Ranking::Streak => format!("{}", p.best_streak),
+        }
+    }
+}
+
+fn winrate(p: &Player) -> f64 {
+    match p.wins + p.losses {
+        0 => 0.0,
+        games => p.wins as f64 / games as f64,
+    }
+}
+
+// Everyone who qualifies for a ranking, best first.
+pub fn ranked<'a>(
+    players: &'a HashMap<String, Player>,
+    by: Ranking,
+    config: &GameConfig,
+) -> Vec<&'a Player> {
+    let mut ranked: Vec<&Player> = players
+        .values()
+        .filter(|p| by != Ranking::Winrate || p.wins + p.losses >= config.min_games)
+        .collect();
+    ranked.sort_by(|a, b| {
+        let order = match by {
+            Ranking::Cash => b.cash.cmp(&a.cash),
+            Ranking::Winrate => winrate(b)
+                .partial_cmp(&winrate(a))
+                .unwrap_or(std::cmp::Ordering::Equal),
+            Ranking::Wins => b.wins.cmp(&a.wins),
+            Ranking::Streak => b.best_streak.cmp(&a.best_streak),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    });
+    ranked
+}
+
+// Checks that a player can give this many points away, returning the fee.
+pub fn check_transfer(
+    from: &Player,
+    amount: i64,
+    config: &GameConfig,
+    now: u64,
+) -> Result<i64, String> {
+    if amount <= 0 {
+        return Err(String::from(
+            "You can only give a positive amount of points!",
+        ));
+    }
+    if from.cash < amount {
+        return Err(format!(
+            "The player '{}' has insufficient funds to give that! ({})",
+            from.name, amount
+        ));
+    }
+    if config.transfer_limit > 0 {
+        let given: i64 = from
+            .sent
+            .iter()
+            .filter(|t| t.time + DAY > now)
+            .map(|t| t.amount)
+            .sum();
+        if given + amount > config.transfer_limit {
+            return Err(format!(
+                "You can only give {} points a day, and have {} left for today.",
+                config.transfer_limit,
+                (config.transfer_limit - given).max(0)
+            ));
+        }
+    }
+    let fee = (amount as f64 * config.transfer_fee.clamp(0.0, 1.0)).round() as i64;
+    Ok(fee)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Wager {
+    // Index into the round's outcomes
+    pub outcome: usize,
+    pub amount: i64,
+}
+
+// Total points bet on an outcome.
+fn pool(wagers: &HashMap<String, Wager>, outcome: usize) -> i64 {
+    wagers
+        .values()
+        .filter(|w| w.outcome == outcome)
+        .map(|w| w.amount)
+        .sum()
+}
+
+// How many points each winner gets back, stake included. Losers aren't in the map.
+pub fn payouts(
+    wagers: &HashMap<String, Wager>,
+    winner: usize,
+    config: &GameConfig,
+) -> HashMap<String, i64> {
+    let winning = pool(wagers, winner);
+    let losing: i64 = wagers.values().map(|w| w.amount).sum::<i64>() - winning;
+    let pot = (losing as f64 * (1.0 - config.cut())) as i64;
+    let mut payouts: HashMap<String, i64> = wagers
+        .iter()
+        .filter(|(_, w)| w.outcome == winner)
+        .map(|(user, w)| {
+            let winnings = match config.payout {
+                PayoutMode::Fixed => w.amount,
+                PayoutMode::Parimutuel => (w.amount as i128 * pot as i128 / winning as i128) as i64,
+            };
+            (user.clone(), w.amount + winnings)
+        })
+        .collect();
+    if config.payout == PayoutMode::Parimutuel {
+        // Shares are rounded down, what's left of the pot goes to the biggest winning bet
+        // (the first name alphabetically, if there's a tie).
+        let leftover = pot + winning - payouts.values().sum::<i64>();
+        let biggest = wagers
+            .iter()
+            .filter(|(_, w)| w.outcome == winner)
+            .max_by(|a, b| a.1.amount.cmp(&b.1.amount).then(b.0.cmp(a.0)))
+            .map(|(user, _)| user.clone());
+        if let Some(user) = biggest {
+            if let Some(payout) = payouts.get_mut(&user) {
+                *payout += leftover;
+            }
+        }
+    }
+    payouts
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Game {
     players: HashMap<String, Player>,
-    wagers: HashMap<String, i64>,
+    wagers: HashMap<String, Wager>,
+    #[serde(default = "Round::new")]
+    round: Round,
+    #[serde(default = "GameConfig::new")]
+    config: GameConfig,
+    #[serde(skip, default = "Casino::new")]
+    casino: Casino,
 }
 
 impl Game {
@@ -23,7 +423,36 @@ impl Game {
         Game {
             players: get_players(&PLAYER_PATH),
             wagers: HashMap::new(),
+            round: Round::new(),
+            config: GameConfig::new(),
+            casino: Casino::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: GameConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    // Casino::seeded makes slots and roulette play out the same way every time.
+    pub fn set_casino(&mut self, casino: Casino) {
+        self.casino = casino;
+    }
+
+    // Hands out points, making players for anyone who hasn't played yet.
+    pub fn award(&mut self, awards: &Vec<(String, i64)>) -> i64 {
+        let mut total = 0;
+        for (user, points) in awards {
+            self.players
+                .entry(user.clone())
+                .or_insert(Player::new(user.clone()))
+                .cash += points;
+            total += points;
         }
+        total
     }
 
     pub fn summarize(p: &Player) -> String {
@@ -54,6 +483,64 @@ impl Game {
         }
     }
 
+    pub fn leaderboard(&self, by: &String) -> String {
+        let by = match Ranking::parse(by) {
+            Some(by) => by,
+            None => return String::from("Rank by cash, winrate, wins or streak."),
+        };
+        let ranked = ranked(&self.players, by, &self.config);
+        if ranked.is_empty() {
+            return match by {
+                Ranking::Winrate => {
+                    format!("Nobody has played {} games yet!", self.config.min_games)
+                }
+                _ => String::from("Nobody has played yet!"),
+            };
+        }
+        let mut message = format!("Top {}:", by.name());
+        for (i, p) in ranked.iter().take(self.config.leaderboard_size).enumerate() {
+            let entry = format!(" {}. {} ({})", i + 1, p.name, by.show(p));
+            if message.len() + entry.len() > MAX_MESSAGE {
+                break;
+            }
+            message.push_str(&entry);
+        }
+        message
+    }
+
+    pub fn rank(&self, name: &String) -> String {
+        let player = match self.players.get(name) {
+            Some(p) => p,
+            None => return self.status(name),
+        };
+        let ranks: Vec<String> = [
+            Ranking::Cash,
+            Ranking::Wins,
+            Ranking::Winrate,
+            Ranking::Streak,
+        ]
+        .iter()
+        .map(|by| {
+            let ranked = ranked(&self.players, *by, &self.config);
+            match ranked.iter().position(|p| p.name == player.name) {
+                Some(i) => format!(
+                    "#{} of {} by {} ({})",
+                    i + 1,
+                    ranked.len(),
+                    by.name(),
+                    by.show(player)
+                ),
+                None => format!(
+                    "unranked by {} ({} more games needed)",
+                    by.name(),
+                    self.config.min_games - player.wins - player.losses
+                ),
+            }
+        })
+        .collect();
+        format!("{} is {}.", player.name, ranks.join(", "))
+    }
+
     pub fn status(&self, name: &String) -> String {
         match self.players.get(name) {
             Some(p) => Game::summarize(p),
@@ -69,90 +556,435 @@ impl Game {
         self.players = get_players(&PLAYER_PATH);
     }
 
+    // Opens a round, a prediction if there are outcomes to pick from.
+    pub fn open_round(
+        &mut self,
+        title: &String,
+        outcomes: Vec<String>,
+        window: Option<u64>,
+        now: u64,
+    ) -> Result<String, String> {
+        self.round.open(title, outcomes, window, now)?;
+        let until = match window {
+            Some(secs) => format!(" You have {} seconds to bet.", secs),
+            None => String::new(),
+        };
+        let how = if self.round.is_prediction() {
+            format!(
+                "{} Use !bet with an outcome and an amount.",
+                self.outcome_list()
+            )
+        } else {
+            String::from("Use !bet_for or !bet_against with an amount.")
+        };
+        Ok(format!(
+            "Betting is open: {} {}{}",
+            self.round.name(),
+            how,
+            until
+        ))
+    }
+
+    // "1. Gwyn, 2. Ornstein, 3. Artorias"
+    fn outcome_list(&self) -> String {
+        self.round
+            .outcomes()
+            .iter()
+            .enumerate()
+            .map(|(i, o)| format!("{}. {}", i + 1, o))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn lock_round(&mut self) -> Result<String, String> {
+        self.round.lock()?;
+        Ok(format!(
+            "Betting is locked for '{}' with {} bet(s) placed. Good luck!",
+            self.round.name(),
+            self.wagers.len()
+        ))
+    }
+
+    // Locks the round when its timer runs out, returning the announcement.
+    pub fn tick(&mut self, now: u64) -> Option<String> {
+        if self.round.tick(now) {
+            Some(format!(
+                "Time's up! Betting is locked for '{}' with {} bet(s) placed.",
+                self.round.name(),
+                self.wagers.len()
+            ))
+        } else {
+            None
+        }
+    }
+
+    // The current pools, and what a point bet on each outcome pays back.
+    pub fn odds(&self) -> String {
+        let total: i64 = self.wagers.values().map(|w| w.amount).sum();
+        let odds: Vec<String> = self
+            .round
+            .outcomes()
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let bets = self.wagers.values().filter(|w| w.outcome == i).count();
+                let pool = pool(&self.wagers, i);
+                let pays = match self.config.payout {
+                    PayoutMode::Fixed => String::from("2x"),
+                    PayoutMode::Parimutuel if pool == 0 => String::from("?"),
+                    PayoutMode::Parimutuel => format!(
+                        "{:.2}x",
+                        1.0 + (total - pool) as f64 * (1.0 - self.config.cut()) / pool as f64
+                    ),
+                };
+                format!(
+                    "{}. {} - {} bet(s), {} points, pays {}",
+                    i + 1,
+                    name,
+                    bets,
+                    pool,
+                    pays
+                )
+            })
+            .collect();
+        format!("{}: {}", self.round.name(), odds.join(" | "))
+    }
+
+    fn can_resolve(&self) -> Result<(), String> {
+        if self.round.is_locked() {
+            Ok(())
+        } else if self.round.is_open() {
+            Err(String::from("Betting is still open, lock it first!"))
+        } else {
+            Err(String::from("There's no betting round to resolve."))
+        }
+    }
+
+    pub fn give(
+        &mut self,
+        from: &String,
+        to: &String,
+        amount: &String,
+        now: u64,
+    ) -> Result<String, String> {
+        if from == to {
+            return Err(String::from("You can't give points to yourself!"));
+        }
+        if !self.players.contains_key(to) {
+            return Err(format!("The player '{}' does not exist!", to));
+        }
+        let sender = match self.players.get_mut(from) {
+            Some(p) => p,
+            None => return Err(format!("The player '{}' has no points to give!", from)),
+        };
+        let amount = parse_amount(amount, sender.cash)?;
+        let fee = check_transfer(sender, amount, &self.config, now)?;
+        sender.cash -= amount;
+        // Only the last day counts towards the limit, older transfers can go.
+        sender.sent.retain(|t| t.time + DAY > now);
+        sender.sent.push(Transfer {
+            time: now,
+            to: to.clone(),
+            amount,
+            fee,
+        });
+        self.players.get_mut(to).unwrap().cash += amount - fee;
+        println!(
+            "[Game] {} gave {} points to {} ({} fee).",
+            from, amount, to, fee
+        );
+        Ok(match fee {
+            0 => format!("{} gave {} points to {}!", from, amount, to),
+            fee => format!(
+                "{} gave {} points to {}! ({} went to the fee)",
+                from,
+                amount - fee,
+                to,
+                fee
+            ),
+        })
+    }
+
+    pub fn daily(&mut self, user: &String, now: u64) -> Result<String, String> {
+        let player = self
+            .players
+            .entry(user.clone())
+            .or_insert(Player::new(user.clone()));
+        let bonus = claim_daily(player, &self.config.daily, now)?;
+        Ok(match player.daily_streak {
+            1 => format!("{} claimed their daily {} points!", user, bonus),
+            streak => format!(
+                "{} claimed their daily {} points, {} days in a row!",
+                user, bonus, streak
+            ),
+        })
+    }
+
+    pub fn bailout(&mut self, user: &String, now: u64) -> Result<String, String> {
+        if self.wagers.contains_key(user) {
+            return Err(String::from("Wait until your bet is settled first!"));
+        }
+        let player = match self.players.get_mut(user) {
+            Some(p) => p,
+            None => return Err(format!("The player '{}' does not exist!", user)),
+        };
+        bail_out(player, &self.config.daily, now)?;
+        Ok(format!(
+            "{} has been bailed out, and is back to {} points. Spend wisely!",
+            user, player.cash
+        ))
+    }
+
     pub fn valid_wager(&mut self, wager: &String, user: &String) -> Result<i64, String> {
-        // Is it a valid number?
-        if let Ok(w) = wager.parse::<i64>() {
-            // Is it greater than 4?
-            if w < 5 {
+        if !self.round.is_open() {
+            return Err(String::from("Betting isn't open right now!"));
+        }
+        let w = self.valid_amount(wager, user)?;
+        // Does the player already have a wager?
+        match self.wagers.get(user) {
+            Some(w) => Err(format!(
+                "The player '{}' has already wagered {}!",
+                user, w.amount
+            )),
+            None => Ok(w),
+        }
+    }
+
+    // Checks an amount of points the player is about to put on the line.
+    fn valid_amount(&mut self, amount: &String, user: &String) -> Result<i64, String> {
+        // Is it a valid player?
+        let player = self
+            .players
+            .entry(user.clone())
+            .or_insert(Player::new(user.clone()));
+        // Is it a valid amount? (See amount.rs for what counts)
+        let w = parse_amount(amount, player.cash)?;
+        if w < MIN_WAGER {
+            return Err(format!(
+                "Your wager is too small! (Wagers must be {} or greater!)",
+                MIN_WAGER
+            ));
+        }
+        if self.config.max_wager > 0 && w > self.config.max_wager {
+            return Err(format!(
+                "Your wager is too big! (Wagers can be at most {}!)",
+                self.config.max_wager
+            ));
+        }
+        if player.cash < w {
+            return Err(format!(
+                "The player '{}' has insufficient funds to make that bet! ({})",
+                user, w
+            ));
+        }
+        Ok(w)
+    }
+
+    fn casino_ready(&self, user: &String, now: u64) -> Result<(), String> {
+        match self.casino.cooldown(user, &self.config.casino, now) {
+            Some(secs) => Err(format!(
+                "Slow down {}! You can play again in {} seconds.",
+                user, secs
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Takes the bet, pays out, and says how it went.
+    fn settle(&mut self, user: &String, amount: i64, payout: i64, now: u64) -> String {
+        self.casino.played(user, now);
+        let player = self.players.get_mut(user).unwrap();
+        player.cash += payout - amount;
+        match payout - amount {
+            n if n > 0 => format!("{} won {} points!", user, n),
+            0 => format!("{} got their points back.", user),
+            n => format!("{} lost {} points.", user, -n),
+        }
+    }
+
+    pub fn slots(&mut self, user: &String, amount: &String, now: u64) -> Result<String, String> {
+        self.casino_ready(user, now)?;
+        if self.config.casino.slots.is_empty() {
+            return Err(String::from("The slot machine is out of order."));
+        }
+        let amount = self.valid_amount(amount, user)?;
+        let reels = self.casino.spin_slots(&self.config.casino);
+        let payout = slots_payout(&reels, amount, &self.config.casino);
+        let shown: Vec<String> = reels
+            .iter()
+            .map(|r| self.config.casino.slots[*r].symbol.clone())
+            .collect();
+        Ok(format!(
+            "[ {} ] {}",
+            shown.join(" | "),
+            self.settle(user, amount, payout, now)
+        ))
+    }
+
+    // "100 red", "half black", "50 17"
+    pub fn roulette(&mut self, user: &String, args: &String, now: u64) -> Result<String, String> {
+        self.casino_ready(user, now)?;
+        let mut words = args.split_whitespace();
+        let amount = words.next().unwrap_or("").to_string();
+        let bet = match words.next().and_then(RouletteBet::parse) {
+            Some(bet) => bet,
+            None => {
                 return Err(String::from(
-                    "Your wager is too small! (Wagers must be 5 or greater!)",
-                ));
-            }
-            // Is it a valid player?
-            let player = self.players.entry(user.clone()).or_insert(Player::new(user.clone()));
-            if player.cash < w {
-                return Err(format!(
-                    "The player '{}' has insufficient funds to make that bet! ({})",
-                    user, w
-                ));
+                    "Bet on red, black or a number from 0 to 36, eg. !roulette 100 red",
+                ))
             }
-            // Does the player already have a wager?
-            match self.wagers.get(user) {
-                Some(i) => Err(format!("The player '{}' has already wagered {}!", user, i)),
-                None => Ok(w),
-            }
-        } else {
-            Err(String::from("Your wager needs to be a valid integer!"))
-        }
+        };
+        let amount = self.valid_amount(&amount, user)?;
+        let number = self.casino.spin_roulette();
+        let payout = roulette_payout(bet, number, amount, &self.config.casino);
+        Ok(format!(
+            "The ball lands on {} {}! {}",
+            number,
+            color(number),
+            self.settle(user, amount, payout, now)
+        ))
     }
 
-    fn make_bet(&mut self, amount: i64, user: &String) {
+    fn make_bet(&mut self, amount: i64, outcome: usize, user: &String) {
         // This function is only called if the wager is valid.
         // Could use typesafety to ensure that, but it doesn't prevent
         // bad use, so this function is private.
-        self.players.get_mut(user).unwrap().cash -= amount.abs();
-        self.wagers.insert(user.clone(), amount);
+        self.players.get_mut(user).unwrap().cash -= amount;
+        self.wagers.insert(user.clone(), Wager { outcome, amount });
     }
 
-    pub fn bet_for(&mut self, user: &String, amount: &String) -> Result<(), String> {
-        match self.valid_wager(amount, user) {
-            Ok(i) => Ok(self.make_bet(i, user)),
-            Err(e) => Err(e),
+    fn bet_plain(&mut self, user: &String, amount: &String, outcome: usize) -> Result<(), String> {
+        if self.round.is_prediction() {
+            return Err(String::from(
+                "This round has named outcomes, use !bet with an outcome and an amount.",
+            ));
         }
+        let i = self.valid_wager(amount, user)?;
+        self.make_bet(i, outcome, user);
+        Ok(())
+    }
+
+    pub fn bet_for(&mut self, user: &String, amount: &String) -> Result<(), String> {
+        self.bet_plain(user, amount, WORKS)
     }
 
     pub fn bet_against(&mut self, user: &String, amount: &String) -> Result<(), String> {
-        match self.valid_wager(amount, user) {
-            Ok(i) => Ok(self.make_bet(-1 * i, user)),
-            Err(e) => Err(e),
+        self.bet_plain(user, amount, FAILS)
+    }
+
+    // "Ornstein 100" or "2 100" - the amount is the last word, the outcome is the rest.
+    pub fn bet(&mut self, user: &String, args: &String) -> Result<(), String> {
+        let mut words = args.trim().rsplitn(2, ' ');
+        let amount = words.next().unwrap_or("").to_string();
+        let outcome = match words.next().and_then(|o| self.round.outcome(o)) {
+            Some(outcome) => outcome,
+            None => {
+                return Err(format!(
+                    "Bet on one of these, then an amount: {}",
+                    self.outcome_list()
+                ))
+            }
+        };
+        let i = self.valid_wager(&amount, user)?;
+        self.make_bet(i, outcome, user);
+        Ok(())
+    }
+
+    // Gives everyone their stake back, wins & losses stay as they were.
+    pub fn cancel(&mut self) -> Result<String, String> {
+        if self.wagers.is_empty() && !self.round.is_open() && !self.round.is_locked() {
+            return Err(String::from("There's no betting round to cancel."));
+        }
+        let mut refunded: i64 = 0;
+        for (user, wager) in self.wagers.drain() {
+            match self.players.get_mut(&user) {
+                Some(p) => {
+                    p.cash += wager.amount;
+                    refunded += wager.amount;
+                }
+                None => println!("Odd, player {} no longer exists.", user),
+            }
         }
+        let round = self.round.name();
+        self.round.close();
+        Ok(format!(
+            "'{}' has been cancelled, {} points went back to where they came from.",
+            round, refunded
+        ))
     }
 
-    pub fn worked(&mut self) -> String {
-        // code repetition here & below is sorta bad
-        // but that's life
+    // Pays out the round, returning (winners, points won, losers, points lost).
+    fn resolve(&mut self, winner: usize) -> (u32, i64, u32, i64) {
+        let payouts = payouts(&self.wagers, winner, &self.config);
         let mut num_wins: u32 = 0;
         let mut amount_won: i64 = 0;
         let mut num_losses: u32 = 0;
         let mut amount_lost: i64 = 0;
         for (user, wager) in &self.wagers {
-            match *wager {
-                i if i < 0 => {
-                    num_losses += 1;
-                    amount_lost -= wager; // beautiful
-                    match self.players.get_mut(&*user) {
-                        Some(p) => {
-                            p.losses += 1;
-                        }
-                        None => println!("Odd, player {} no longer exists.", user),
-                    }
+            if wager.amount == 0 {
+                println!("Odd, wager for user {} was 0.", user);
+                continue;
+            }
+            let player = match self.players.get_mut(&*user) {
+                Some(p) => p,
+                None => {
+                    println!("Odd, player {} no longer exists.", user);
+                    continue;
                 }
-                i if i > 0 => {
+            };
+            match payouts.get(user) {
+                Some(payout) => {
                     num_wins += 1;
-                    amount_won += wager * 2;
-                    match self.players.get_mut(&*user) {
-                        Some(p) => {
-                            p.cash += wager * 2;
-                            p.wins += 1;
-                        }
-                        None => println!("Odd, player {} no longer exists.", user),
-                    }
+                    amount_won += payout;
+                    player.cash += payout;
+                    player.wins += 1;
+                    player.streak += 1;
+                    player.best_streak = player.best_streak.max(player.streak);
+                }
+                None => {
+                    num_losses += 1;
+                    amount_lost += wager.amount;
+                    player.losses += 1;
+                    player.streak = 0;
                 }
-                _ => println!("Odd, wager for user {} was 0.", user),
             }
         }
         self.wagers.clear();
-        if num_wins + num_losses == 0 {
+        (num_wins, amount_won, num_losses, amount_lost)
+    }
+
+    fn can_resolve_plain(&self) -> Result<(), String> {
+        if self.round.is_prediction() {
+            return Err(String::from(
+                "This round has named outcomes, resolve it by picking the one that happened.",
+            ));
+        }
+        self.can_resolve()
+    }
+
+    // Resolves a prediction by outcome name or number.
+    pub fn resolve_outcome(&mut self, outcome: &String) -> Result<String, String> {
+        self.can_resolve()?;
+        let winner = match self.round.outcome(outcome) {
+            Some(winner) => winner,
+            None => return Err(format!("Pick one of these: {}", self.outcome_list())),
+        };
+        let name = self.round.outcomes()[winner].clone();
+        let (num_wins, amount_won, num_losses, amount_lost) = self.resolve(winner);
+        let round = self.round.name();
+        self.round.close();
+        Ok(format!(
+            "'{}': {} it is! {} player(s) won {} points, while {} player(s) lost {} points.",
+            round, name, num_wins, amount_won, num_losses, amount_lost
+        ))
+    }
+
+    pub fn worked(&mut self) -> Result<String, String> {
+        self.can_resolve_plain()?;
+        let (num_wins, amount_won, num_losses, amount_lost) = self.resolve(WORKS);
+        let round = self.round.name();
+        self.round.close();
+        let result = if num_wins + num_losses == 0 {
             String::from("Nice work, but nobody was playing...")
         } else if num_wins == 0 {
             format!(
@@ -169,42 +1001,16 @@ impl Game {
                 "{} player(s) won {} points, while {} player(s) lost {} points!",
                 num_wins, amount_won, num_losses, amount_lost
             )
-        }
+        };
+        Ok(format!("'{}' worked! {}", round, result))
     }
 
-    pub fn failed(&mut self) -> String {
-        let mut num_wins: u32 = 0;
-        let mut amount_won: i64 = 0;
-        let mut num_losses: u32 = 0;
-        let mut amount_lost: i64 = 0;
-        for (user, wager) in &self.wagers {
-            match *wager {
-                i if i > 0 => {
-                    num_losses += 1;
-                    amount_lost += wager; // beautiful
-                    match self.players.get_mut(&*user) {
-                        Some(p) => {
-                            p.losses += 1;
-                        }
-                        None => println!("Odd, player {} no longer exists.", user),
-                    }
-                }
-                i if i < 0 => {
-                    num_wins += 1;
-                    amount_won -= wager * 2;
-                    match self.players.get_mut(&*user) {
-                        Some(p) => {
-                            p.cash += wager * -2;
-                            p.wins += 1;
-                        }
-                        None => println!("Odd, player {} no longer exists.", user),
-                    }
-                }
-                _ => println!("Odd, wager for user {} was 0.", user),
-            }
-        }
-        self.wagers.clear();
-        if num_wins + num_losses == 0 {
+    pub fn failed(&mut self) -> Result<String, String> {
+        self.can_resolve_plain()?;
+        let (num_wins, amount_won, num_losses, amount_lost) = self.resolve(FAILS);
+        let round = self.round.name();
+        self.round.close();
+        let result = if num_wins + num_losses == 0 {
             String::from("You're only hurting yourself...")
         } else if num_wins == 0 {
             format!(
@@ -221,7 +1027,8 @@ impl Game {
                 "{} player(s) won {} points, while {} player(s) lost {} points.",
                 num_wins, amount_won, num_losses, amount_lost
             )
-        }
+        };
+        Ok(format!("'{}' failed. {}", round, result))
     }
 }
 
@@ -252,3 +1059,306 @@ impl Drop for Game {
         }
     }
 }
+
+#[cfg(test)]
+mod game_tests {
+    use super::*;
+    use crate::round::RoundState;
+
+    // A game with its own players, rather than whoever is in players.json.
+    fn game(players: &[(&str, i64)]) -> Game {
+        let mut game = Game {
+            players: HashMap::new(),
+            wagers: HashMap::new(),
+            round: Round::new(),
+            config: GameConfig::new(),
+            casino: Casino::new(),
+        };
+        for (name, cash) in players {
+            let mut p = Player::new(name.to_string());
+            p.cash = *cash;
+            game.players.insert(name.to_string(), p);
+        }
+        game
+    }
+
+    // Dropping a Game dumps it to gamedump.json, which tests shouldn't leave behind.
+    fn done(game: Game) {
+        std::mem::forget(game);
+    }
+
+    fn wagers() -> HashMap<String, Wager> {
+        let mut wagers = HashMap::new();
+        let mut bet = |user: &str, outcome: usize, amount: i64| {
+            wagers.insert(String::from(user), Wager { outcome, amount });
+        };
+        bet("a", WORKS, 100);
+        bet("b", WORKS, 300);
+        bet("c", FAILS, 200);
+        wagers
+    }
+
+    #[test]
+    fn test_fixed_payouts() {
+        let config = GameConfig::new();
+        let worked = payouts(&wagers(), WORKS, &config);
+        assert_eq!(worked.len(), 2);
+        assert_eq!(worked["a"], 200);
+        assert_eq!(worked["b"], 600);
+        let failed = payouts(&wagers(), FAILS, &config);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed["c"], 400);
+    }
+
+    #[test]
+    fn test_parimutuel_payouts() {
+        let mut config = GameConfig::new();
+        config.payout = PayoutMode::Parimutuel;
+        // 200 lost, split 1:3
+        let worked = payouts(&wagers(), WORKS, &config);
+        assert_eq!(worked["a"], 150);
+        assert_eq!(worked["b"], 450);
+        // All 400 go to c
+        assert_eq!(payouts(&wagers(), FAILS, &config)["c"], 600);
+
+        config.house_cut = 0.5;
+        let worked = payouts(&wagers(), WORKS, &config);
+        assert_eq!(worked["a"], 125);
+        assert_eq!(worked["b"], 375);
+
+        // Rounding leftovers go to the biggest bet, so the whole pot is paid out.
+        config.house_cut = 0.0;
+        let mut uneven = HashMap::new();
+        uneven.insert(
+            String::from("a"),
+            Wager {
+                outcome: WORKS,
+                amount: 100,
+            },
+        );
+        uneven.insert(
+            String::from("b"),
+            Wager {
+                outcome: WORKS,
+                amount: 200,
+            },
+        );
+        uneven.insert(
+            String::from("c"),
+            Wager {
+                outcome: FAILS,
+                amount: 100,
+            },
+        );
+        let worked = payouts(&uneven, WORKS, &config);
+        assert_eq!(worked["a"], 133);
+        assert_eq!(worked["b"], 267);
+        uneven.get_mut("b").unwrap().amount = 100;
+        uneven.get_mut("c").unwrap().amount = 101;
+        let worked = payouts(&uneven, WORKS, &config);
+        assert_eq!(worked["a"], 151);
+        assert_eq!(worked["b"], 150);
+        assert!(payouts(&uneven, 2, &config).is_empty());
+        config.house_cut = 0.5;
+
+        // Nobody to take points from, so winners just get their stake back.
+        let mut one_sided = wagers();
+        one_sided.remove("c");
+        assert_eq!(payouts(&one_sided, WORKS, &config)["b"], 300);
+    }
+
+    #[test]
+    fn test_many_outcomes() {
+        let mut config = GameConfig::new();
+        config.payout = PayoutMode::Parimutuel;
+        let mut wagers = wagers();
+        wagers.insert(
+            String::from("d"),
+            Wager {
+                outcome: 2,
+                amount: 600,
+            },
+        );
+        // 600 points lost to d
+        let third = payouts(&wagers, 2, &config);
+        assert_eq!(third.len(), 1);
+        assert_eq!(third["d"], 1200);
+        // 800 lost, split 1:3 again
+        assert_eq!(payouts(&wagers, WORKS, &config)["b"], 900);
+        assert!(payouts(&wagers, 3, &config).is_empty());
+    }
+
+    #[test]
+    fn test_rankings() {
+        let mut players = HashMap::new();
+        let mut add = |name: &str, cash: i64, wins: i64, losses: i64| {
+            let mut p = Player::new(String::from(name));
+            p.cash = cash;
+            p.wins = wins;
+            p.losses = losses;
+            p.best_streak = wins / 2;
+            players.insert(p.name.clone(), p);
+        };
+        add("a", 500, 9, 1);
+        add("b", 2000, 20, 20);
+        add("c", 800, 1, 0);
+        add("d", 800, 0, 0);
+        let mut config = GameConfig::new();
+        let names = |by: Ranking, config: &GameConfig| -> Vec<String> {
+            ranked(&players, by, config)
+                .iter()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        assert_eq!(names(Ranking::Cash, &config), vec!["b", "c", "d", "a"]);
+        assert_eq!(names(Ranking::Wins, &config), vec!["b", "a", "c", "d"]);
+        assert_eq!(names(Ranking::Streak, &config), vec!["b", "a", "c", "d"]);
+        // c has a perfect record, but only one game
+        assert_eq!(names(Ranking::Winrate, &config), vec!["a", "b"]);
+        config.min_games = 1;
+        assert_eq!(names(Ranking::Winrate, &config), vec!["c", "a", "b"]);
+        assert_eq!(Ranking::parse("WINRATE"), Some(Ranking::Winrate));
+        assert_eq!(Ranking::parse("luck"), None);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut g = game(&[("a", 1000), ("b", 1000)]);
+        assert!(g.cancel().is_err());
+        g.players.get_mut("a").unwrap().wins = 3;
+        g.players.get_mut("b").unwrap().losses = 2;
+        let unchanged = |g: &Game| {
+            assert_eq!(g.players["a"].cash, 1000);
+            assert_eq!(g.players["b"].cash, 1000);
+            assert_eq!((g.players["a"].wins, g.players["a"].losses), (3, 0));
+            assert_eq!((g.players["b"].wins, g.players["b"].losses), (0, 2));
+            assert!(g.wagers.is_empty());
+            assert_eq!(g.round.state, RoundState::Closed);
+        };
+
+        // While betting is still open
+        assert!(g.open_round(&String::new(), Vec::new(), None, 0).is_ok());
+        assert!(g.bet_for(&String::from("a"), &String::from("100")).is_ok());
+        assert!(g
+            .bet_against(&String::from("b"), &String::from("250"))
+            .is_ok());
+        assert_eq!(g.players["a"].cash, 900);
+        assert!(g.cancel().unwrap().contains("350 points"));
+        unchanged(&g);
+        assert!(g.cancel().is_err());
+
+        // After it's locked
+        assert!(g.open_round(&String::new(), Vec::new(), None, 0).is_ok());
+        assert!(g.bet_for(&String::from("a"), &String::from("all")).is_ok());
+        assert!(g.lock_round().is_ok());
+        assert!(g.cancel().is_ok());
+        unchanged(&g);
+        done(g);
+    }
+
+    #[test]
+    fn test_casino() {
+        let mut g = game(&[("a", 1000)]);
+        g.set_casino(Casino::seeded(3));
+        // Spins the same way as the game's casino does.
+        let mut spins = Casino::seeded(3);
+        let config = CasinoConfig::new();
+        let (a, b) = (String::from("a"), String::from("b"));
+
+        let reels = spins.spin_slots(&config);
+        let payout = slots_payout(&reels, 100, &config);
+        assert!(g.slots(&a, &String::from("100"), 0).is_ok());
+        assert_eq!(g.players["a"].cash, 900 + payout);
+        let cash = g.players["a"].cash;
+        assert!(g.slots(&a, &String::from("100"), 29).is_err());
+        assert!(g.roulette(&a, &String::from("100 red"), 29).is_err());
+        assert_eq!(g.players["a"].cash, cash);
+
+        let number = spins.spin_roulette();
+        let payout = roulette_payout(RouletteBet::Number(17), number, 50, &config);
+        assert!(g.roulette(&a, &String::from("50 17"), 30).is_ok());
+        assert_eq!(g.players["a"].cash, cash - 50 + payout);
+
+        // Games that never got played don't start the cooldown.
+        assert!(g.slots(&b, &String::from("1"), 100).is_err());
+        assert!(g.roulette(&b, &String::from("100 green"), 100).is_err());
+        assert!(g.roulette(&b, &String::from("5000 red"), 100).is_err());
+        assert_eq!(g.players["b"].cash, 1000);
+        let number = spins.spin_roulette();
+        let payout = roulette_payout(RouletteBet::Black, number, 1000, &config);
+        assert!(g.roulette(&b, &String::from("all black"), 100).is_ok());
+        assert_eq!(g.players["b"].cash, payout);
+        done(g);
+    }
+
+    #[test]
+    fn test_transfers() {
+        let mut config = GameConfig::new();
+        let mut p = Player::new(String::from("a"));
+        assert_eq!(check_transfer(&p, 100, &config, 0), Ok(0));
+        assert!(check_transfer(&p, 0, &config, 0).is_err());
+        assert!(check_transfer(&p, -5, &config, 0).is_err());
+        assert!(check_transfer(&p, p.cash + 1, &config, 0).is_err());
+
+        config.transfer_fee = 0.1;
+        config.transfer_limit = 500;
+        assert_eq!(check_transfer(&p, 105, &config, 0), Ok(11));
+        p.sent.push(Transfer {
+            time: 1000,
+            to: String::from("b"),
+            amount: 400,
+            fee: 40,
+        });
+        assert_eq!(check_transfer(&p, 100, &config, 1000), Ok(10));
+        assert!(check_transfer(&p, 101, &config, 1000).is_err());
+        // A day later, the limit is back.
+        assert_eq!(check_transfer(&p, 500, &config, 1000 + DAY), Ok(50));
+
+        let mut g = game(&[("a", 1000), ("b", 1000)]);
+        g.config = config;
+        let (a, b) = (String::from("a"), String::from("b"));
+        assert!(g.give(&a, &b, &String::from("200"), 1000).is_ok());
+        assert!(g.give(&a, &b, &String::from("300"), 2000).is_ok());
+        assert!(g.give(&a, &b, &String::from("1"), 2000).is_err());
+        assert!(g.give(&a, &a, &String::from("1"), 2000).is_err());
+        assert_eq!(g.players["a"].cash, 500);
+        assert_eq!(g.players["b"].cash, 1450);
+        // Transfers from over a day ago aren't kept around.
+        assert!(g.give(&a, &b, &String::from("200"), 1000 + DAY).is_ok());
+        assert_eq!(g.players["a"].sent.len(), 2);
+        done(g);
+    }
+
+    #[test]
+    fn test_daily() {
+        let config = DailyConfig::new();
+        let mut p = Player::new(String::from("a"));
+        let cash = p.cash;
+        assert_eq!(claim_daily(&mut p, &config, 1000), Ok(100));
+        assert!(claim_daily(&mut p, &config, 1000 + DAY - 1).is_err());
+        assert_eq!(claim_daily(&mut p, &config, 1000 + DAY), Ok(120));
+        assert_eq!(claim_daily(&mut p, &config, 1000 + 3 * DAY - 1), Ok(140));
+        assert_eq!(p.cash, cash + 360);
+        // The streak tops out...
+        p.daily_streak = 20;
+        assert_eq!(claim_daily(&mut p, &config, 1000 + 4 * DAY), Ok(220));
+        // ...and ends after a missed day.
+        assert_eq!(claim_daily(&mut p, &config, 1000 + 6 * DAY), Ok(100));
+        assert_eq!(p.daily_streak, 1);
+    }
+
+    #[test]
+    fn test_bailout() {
+        let config = DailyConfig::new();
+        let mut p = Player::new(String::from("a"));
+        assert!(bail_out(&mut p, &config, 0).is_err());
+        p.cash = 3;
+        assert!(bail_out(&mut p, &config, 1000).is_ok());
+        assert_eq!(p.cash, 100);
+        p.cash = 0;
+        assert!(bail_out(&mut p, &config, 1000 + DAY - 1).is_err());
+        assert_eq!(p.cash, 0);
+        assert!(bail_out(&mut p, &config, 1000 + DAY).is_ok());
+        assert_eq!(wait(2 * 60 * 60 + 5 * 60 + 3), "2h 5m");
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::chat::ChatMessage;
+
+/* Spam heuristics for the filter.
+ *
+ * Each of these produces a score for a message, the filter rules decide the thresholds.
+ * The repeat/wave checks need to know what was said recently, which is what History is for.
+ */
+
+// How far back History remembers, regardless of rule windows.
+const HISTORY_SECS: u64 = 600;
+const HISTORY_MAX: usize = 2000;
+
+// Fraction of visible characters that aren't letters, numbers or spaces.
+pub fn symbol_ratio(text: &str) -> f64 {
+    let visible: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if visible.is_empty() {
+        return 0.0;
+    }
+    let symbols = visible
+        .iter()
+        .filter(|c| !c.is_alphanumeric() && !is_combining(**c))
+        .count();
+    symbols as f64 / visible.len() as f64
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F
+            | 0x0483..=0x0489
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x20D0..=0x20FF
+            | 0xFE20..=0xFE2F
+    )
+}
+
+// Combining marks - zalgo text stacks dozens of these on every letter.
+pub fn zalgo_marks(text: &str) -> usize {
+    text.chars().filter(|c| is_combining(*c)).count()
+}
+
+/* Number of emotes in a message.
+ * Twitch tells us where they are with the emotes tag, "25:0-4,12-16/1902:6-10",
+ * which is there but empty when there are none. Without tags at all, we guess -
+ * the most repeated word is probably an emote.
+ */
+pub fn emote_count(msg: &ChatMessage) -> usize {
+    match msg.tags.get("emotes") {
+        Some(emotes) => emotes
+            .split('/')
+            .filter_map(|e| e.split_once(':').map(|(_, ranges)| ranges))
+            .map(|ranges| ranges.split(',').count())
+            .sum(),
+        None => {
+            let mut words: HashMap<&str, usize> = HashMap::new();
+            for w in msg.text.split_whitespace() {
+                *words.entry(w).or_insert(0) += 1;
+            }
+            words.values().cloned().max().unwrap_or(0)
+        }
+    }
+}
+
+fn same_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+struct Seen {
+    time: u64,
+    user: String,
+    text: String,
+}
+
+pub struct History {
+    seen: VecDeque<Seen>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            seen: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, msg: &ChatMessage, now: u64) {
+        while let Some(s) = self.seen.front() {
+            if s.time + HISTORY_SECS >= now && self.seen.len() < HISTORY_MAX {
+                break;
+            }
+            self.seen.pop_front();
+        }
+        self.seen.push_back(Seen {
+            time: now,
+            user: msg.name.clone(),
+            text: same_text(&msg.text),
+        });
+    }
+
+    fn recent<'a>(
+        &'a self,
+        text: &'a str,
+        window: u64,
+        now: u64,
+    ) -> impl Iterator<Item = &'a Seen> {
+        self.seen
+            .iter()
+            .filter(move |s| s.time + window >= now && s.text == text)
+    }
+
+    // How many times this user has sent this message in the window, counting this one.
+    pub fn repeats(&self, msg: &ChatMessage, window: u64, now: u64) -> usize {
+        let text = same_text(&msg.text);
+        1 + self
+            .recent(&text, window, now)
+            .filter(|s| s.user == msg.name)
+            .count()
+    }
+
+    // How many different users have sent this message in the window, counting this one.
+    pub fn wave(&self, msg: &ChatMessage, window: u64, now: u64) -> usize {
+        let text = same_text(&msg.text);
+        let mut users: HashSet<&String> =
+            self.recent(&text, window, now).map(|s| &s.user).collect();
+        users.insert(&msg.name);
+        users.len()
+    }
+}
+
+#[cfg(test)]
+mod heuristics_tests {
+    use super::*;
+
+    fn msg(name: &str, text: &str) -> ChatMessage {
+        ChatMessage::new(
+            &String::from(name),
+            &String::from("desktopfolder"),
+            &String::from(text),
+        )
+    }
+
+    #[test]
+    fn test_text_scores() {
+        assert_eq!(symbol_ratio("hello there"), 0.0);
+        assert_eq!(symbol_ratio("!!!!"), 1.0);
+        assert_eq!(zalgo_marks("hello"), 0);
+        assert_eq!(zalgo_marks("h\u{0301}\u{0302}e\u{0303}"), 3);
+        assert_eq!(symbol_ratio("h\u{0301}\u{0302}e\u{0303}"), 0.0);
+    }
+
+    #[test]
+    fn test_emotes() {
+        let mut m = msg("a", "Kappa Kappa Kappa PogChamp");
+        assert_eq!(emote_count(&m), 3);
+        m.tags.insert(
+            String::from("emotes"),
+            String::from("25:0-4,6-10,12-16/88:18-25"),
+        );
+        assert_eq!(emote_count(&m), 4);
+
+        // Tagged with no emotes means no emotes, however repetitive.
+        let mut m = msg("a", "no no no no no no");
+        assert_eq!(emote_count(&m), 6);
+        m.tags.insert(String::from("emotes"), String::new());
+        assert_eq!(emote_count(&m), 0);
+    }
+
+    #[test]
+    fn test_repeats_and_waves() {
+        let mut h = History::new();
+        h.record(&msg("a", "buy followers"), 0);
+        h.record(&msg("a", "Buy  followers"), 10);
+        h.record(&msg("b", "buy followers"), 20);
+        assert_eq!(h.repeats(&msg("a", "buy followers"), 60, 30), 3);
+        assert_eq!(h.repeats(&msg("a", "buy followers"), 20, 30), 2);
+        assert_eq!(h.wave(&msg("c", "buy followers"), 60, 30), 3);
+        assert_eq!(h.wave(&msg("a", "hello"), 60, 30), 1);
+        // Old messages are forgotten.
+        h.record(&msg("d", "hello"), 10000);
+        assert_eq!(h.wave(&msg("c", "buy followers"), 100000, 10000), 1);
+    }
+}
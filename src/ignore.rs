@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/* Users the bot doesn't listen to at all - other chat bots, mostly.
+ *
+ * Messages from ignored users never reach the filter rules or the commands,
+ * which stops bots from setting each other off in a loop. The bot's own nick
+ * is always ignored, the rest are kept in ignore.json as a list of names:
+ *
+ *  ["nightbot", "streamelements"]
+ *
+ * Mods can change the list from chat, it's saved after every change.
+ */
+
+pub struct IgnoreList {
+    path: Option<PathBuf>,
+    users: BTreeSet<String>,
+    nick: Option<String>,
+}
+
+impl Default for IgnoreList {
+    fn default() -> IgnoreList {
+        IgnoreList::new()
+    }
+}
+
+impl IgnoreList {
+    pub fn new() -> IgnoreList {
+        IgnoreList {
+            path: None,
+            users: BTreeSet::new(),
+            nick: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> IgnoreList {
+        let mut list = IgnoreList::new();
+        list.path = Some(path.to_path_buf());
+        if !path.exists() {
+            return list;
+        }
+        let mut contents = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => match serde_json::from_str::<Vec<String>>(&contents) {
+                Ok(users) => list.users = users.iter().map(|u| u.to_lowercase()).collect(),
+                Err(e) => println!("[ERROR] Could not parse ignore list: {}", e),
+            },
+            Err(e) => println!("[ERROR] Could not read ignore list: {}", e),
+        }
+        list
+    }
+
+    pub fn save(&self) -> bool {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return false,
+        };
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Could not open or create ignore file! {}", e);
+                return false;
+            }
+        };
+        match serde_json::to_writer_pretty(file, &self.users) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("Couldn't save ignore list: {}", e);
+                false
+            }
+        }
+    }
+
+    // The bot's own nick, ignored but never saved.
+    pub fn set_nick(&mut self, nick: &String) {
+        self.nick = Some(nick.to_lowercase());
+    }
+
+    pub fn contains(&self, user: &String) -> bool {
+        let user = user.to_lowercase();
+        self.nick.as_ref() == Some(&user) || self.users.contains(&user)
+    }
+
+    pub fn add(&mut self, user: &String) -> bool {
+        self.users.insert(user.to_lowercase())
+    }
+
+    pub fn remove(&mut self, user: &String) -> bool {
+        self.users.remove(&user.to_lowercase())
+    }
+
+    pub fn users(&self) -> Vec<String> {
+        self.users.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod ignore_tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_list() {
+        let path = Path::new("test_ignore.json");
+        let _ = std::fs::remove_file(path);
+        let mut list = IgnoreList::load(path);
+        list.set_nick(&String::from("RustyBot"));
+        assert!(list.contains(&String::from("rustybot")));
+        assert!(list.add(&String::from("Nightbot")));
+        assert!(!list.add(&String::from("nightbot")));
+        assert!(list.contains(&String::from("NIGHTBOT")));
+        assert!(list.save());
+
+        // The nick isn't saved, the rest is.
+        let mut list = IgnoreList::load(path);
+        assert_eq!(list.users(), vec![String::from("nightbot")]);
+        assert!(!list.contains(&String::from("rustybot")));
+        assert!(list.remove(&String::from("nightbot")));
+        assert!(!list.contains(&String::from("nightbot")));
+        let _ = std::fs::remove_file(path);
+    }
+}
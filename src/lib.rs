@@ -2,3 +2,17 @@ pub mod command_tree;
 pub mod player_data;
 pub mod game;
 pub mod audio;
+pub mod config;
+pub mod chat;
+pub mod filter;
+pub mod strikes;
+pub mod links;
+pub mod heuristics;
+pub mod modlog;
+pub mod raid;
+pub mod confusables;
+pub mod ignore;
+pub mod round;
+pub mod accrual;
+pub mod amount;
+pub mod casino;
@@ -0,0 +1,108 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/* Link detection for the filter.
+ *
+ * Spam bots rarely post clean links, so before looking for domains we undo the
+ * usual tricks: "example dot com", "example(.)com", "example [dot] com", "example . com".
+ * To keep false positives down, a bare domain only counts with a known TLD,
+ * anything with http(s):// in front counts no matter what.
+ *
+ * A spaced out period only counts with spaces on both sides and a known TLD after it,
+ * as "Thanks. Me too" or "gg. To be fair" are just sentences.
+ */
+
+const TLDS: &str = "com|net|org|info|biz|tv|gg|io|ly|me|co|cc|to|ws|ru|su|uk|us|de|fr|xyz|top|site|online|shop|store|live|link|click|app|dev";
+
+fn deobfuscate(text: &str) -> String {
+    lazy_static! {
+        static ref DOT_RE: Regex =
+            Regex::new(r"(?i)\s*[\(\[\{<]\s*(?:dot|\.)\s*[\)\]\}>]\s*|\s+dot\s+").unwrap();
+        static ref SPACED_DOT_RE: Regex =
+            Regex::new(&format!(r"(?i)\s+\.\s+({})\b", TLDS)).unwrap();
+    }
+    let text = DOT_RE.replace_all(text, ".");
+    SPACED_DOT_RE.replace_all(&text, ".$1").to_string()
+}
+
+pub fn find_links(text: &str) -> Vec<String> {
+    lazy_static! {
+        static ref URL_RE: Regex = Regex::new(r"(?i)https?://([a-z0-9.-]+)").unwrap();
+        static ref DOMAIN_RE: Regex = Regex::new(&format!(
+            r"(?i)\b((?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+(?:{}))\b",
+            TLDS
+        ))
+        .unwrap();
+    }
+    let text = deobfuscate(text);
+    let mut links: Vec<String> = URL_RE
+        .captures_iter(&text)
+        .chain(DOMAIN_RE.captures_iter(&text))
+        .map(|c| c[1].trim_matches('.').to_lowercase())
+        .filter(|d| !d.is_empty())
+        .collect();
+    links.sort();
+    links.dedup();
+    links
+}
+
+// A domain is allowed if it's on the list, or a subdomain of something on the list.
+pub fn is_allowed(domain: &String, allow: &Vec<String>) -> bool {
+    allow.iter().any(|a| {
+        let a = a.to_lowercase();
+        *domain == a || domain.ends_with(&format!(".{}", a))
+    })
+}
+
+#[cfg(test)]
+mod links_tests {
+    use super::*;
+
+    #[test]
+    fn test_find_links() {
+        assert_eq!(
+            find_links("go to https://spam.example/x"),
+            vec!["spam.example"]
+        );
+        assert_eq!(
+            find_links("cheap viewers at bigfollows.com"),
+            vec!["bigfollows.com"]
+        );
+        assert_eq!(
+            find_links("nothing to see here. honest"),
+            Vec::<String>::new()
+        );
+        assert_eq!(find_links("I'm 5.5 feet tall"), Vec::<String>::new());
+        // Sentence breaks aren't dots in a domain.
+        for sentence in &[
+            "Thanks. Me too",
+            "gg. To be fair",
+            "that was it. TV time",
+            "we lost. Us next",
+            "I agree. Co-op later?",
+            "wait .me first",
+        ] {
+            assert_eq!(find_links(sentence), Vec::<String>::new(), "{}", sentence);
+        }
+    }
+
+    #[test]
+    fn test_obfuscated() {
+        let expected = vec![String::from("bigfollows.com")];
+        assert_eq!(find_links("bigfollows dot com"), expected);
+        assert_eq!(find_links("bigfollows DOT com"), expected);
+        assert_eq!(find_links("bigfollows(.)com"), expected);
+        assert_eq!(find_links("bigfollows [dot] com"), expected);
+        assert_eq!(find_links("bigfollows . com"), expected);
+        assert_eq!(find_links("bigfollows  .  COM now"), expected);
+    }
+
+    #[test]
+    fn test_allowed() {
+        let allow = vec![String::from("twitch.tv"), String::from("YouTube.com")];
+        assert!(is_allowed(&String::from("twitch.tv"), &allow));
+        assert!(is_allowed(&String::from("clips.twitch.tv"), &allow));
+        assert!(is_allowed(&String::from("youtube.com"), &allow));
+        assert!(!is_allowed(&String::from("nottwitch.tv"), &allow));
+    }
+}
@@ -7,55 +7,40 @@ use async_std::{
     task,
 };
 use async_trait::async_trait;
-use futures::{select, FutureExt};
-use lazy_static::lazy_static;
-use regex::Regex;
+use futures::{future::LocalBoxFuture, select, FutureExt};
 use std::io::Result;
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use rustybot::command_tree::{CmdValue, CommandTree};
-use rustybot::game::Game;
+use rustybot::accrual::Presence;
+use rustybot::chat::{parse_membership, ChatMessage, Membership, Role};
+use rustybot::command_tree::{CmdValue, CommandTree, SeqNext, SeqRun, SeqStep};
+use rustybot::config::{channel_file, config_file};
+use rustybot::filter::{FilterEngine, FilterResult, Verdict};
+use rustybot::game::{Game, GameConfig};
+use rustybot::ignore::IgnoreList;
+use rustybot::modlog::{ModEntry, ModLog};
+use rustybot::raid::{RaidDetector, RaidEvent};
+use rustybot::strikes::StrikeLedger;
 use rustybot::audio::Audio;
 
-// Temporary until I find the correct way to do this.
-trait CaptureExt {
-    fn str_at(&self, i: usize) -> String;
-}
-
-impl CaptureExt for regex::Captures<'_> {
-    fn str_at(&self, i: usize) -> String {
-        self.get(i).unwrap().as_str().to_string()
-    }
-}
-
-// Message filtering
-enum FilterResult {
-    Skip,
-    Ban(String),
-    Empty,
-}
-
-fn filter(_name: &String, message: &String) -> FilterResult {
-    lazy_static! {
-        static ref SPAM_RE_1: Regex =
-            Regex::new(r"follower.{0,15}prime.{0,15}view.{0,25}bigfollows.{0,10}com").unwrap();
-    }
-
-    match SPAM_RE_1.captures(message.as_str()) {
-        // TODO Use re search or something, this is offline code
-        Some(_) => FilterResult::Ban(String::from("Your message has been marked as spam. To be unbanned, send a private message to DesktopFolder.")),
-        _ => FilterResult::Empty,
-    }
-}
-
 enum Command {
     Stop,
     Continue,
+    // The command didn't run (no match, not allowed, bad arguments...)
+    Failed,
 }
 
 struct IRCMessage(String);
 
+// Everything the client reacts to - lines from Twitch, a regular tick for timers,
+// and sequences coming back from a delay.
+enum Event {
+    Line(String),
+    Tick,
+    Resume(ChatMessage, SeqRun),
+}
+
 #[async_trait]
 trait IRCStream {
     async fn send(&mut self, text: IRCMessage) -> ();
@@ -90,19 +75,47 @@ impl TwitchFmt {
     fn pong() -> IRCMessage {
         IRCMessage("PONG :tmi.twitch.tv\r\n".to_string())
     }
+    fn ban(name: &String, reason: &String, channel: &String) -> IRCMessage {
+        TwitchFmt::privmsg(&format!("/ban {} {}", name, reason), channel)
+    }
+    fn unban(name: &String, channel: &String) -> IRCMessage {
+        TwitchFmt::privmsg(&format!("/unban {}", name), channel)
+    }
+    fn timeout(name: &String, secs: u64, reason: &String, channel: &String) -> IRCMessage {
+        TwitchFmt::privmsg(&format!("/timeout {} {} {}", name, secs, reason), channel)
+    }
+    fn delete(msg_id: &String, channel: &String) -> IRCMessage {
+        TwitchFmt::privmsg(&format!("/delete {}", msg_id), channel)
+    }
+    fn cap_req() -> IRCMessage {
+        // Tags give us badges (for roles) and message ids (for deletes).
+        // Membership tells us who's in chat (JOIN/PART/NAMES), for passive points.
+        IRCMessage(
+            "CAP REQ :twitch.tv/tags twitch.tv/commands twitch.tv/membership\r\n".to_string(),
+        )
+    }
 }
 
 struct IRCBotClient {
     stream: TcpStream,
     nick: String,
     secret: String,
-    reader: BufReader<TcpStream>,
+    events: Receiver<Event>,
+    // For scheduling our own events, like the rest of a delayed sequence
+    later: Sender<Event>,
     sender: Sender<IRCMessage>,
     channel: String,
     ct: CommandTree,
+    filters: FilterEngine,
+    strikes: StrikeLedger,
+    modlog: ModLog,
+    raid: RaidDetector,
     game: Game,
+    presence: Presence,
     audio: Audio,
     autosave: bool,
+    // The sequence currently being run, if any
+    sequence: Option<SeqRun>,
 }
 
 // Class that receives messages, then sends them.
@@ -128,41 +141,96 @@ impl IRCBotMessageSender {
     }
 }
 
+// Class that reads lines, then passes them on to the client.
+struct IRCBotMessageReader {
+    reader: BufReader<TcpStream>,
+    events: Sender<Event>,
+}
+
+impl IRCBotMessageReader {
+    async fn launch_read(&mut self) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line).await {
+                Ok(n) => {
+                    self.events.send(Event::Line(line.clone())).await;
+                    if n == 0 {
+                        // The connection is gone, the client stops on the empty line.
+                        break;
+                    }
+                }
+                Err(e) => {
+                    println!("Encountered error: {}", e);
+                    continue;
+                }
+            }
+        }
+    }
+}
+
+async fn launch_tick(events: Sender<Event>) {
+    loop {
+        task::sleep(Duration::from_secs(1)).await;
+        events.send(Event::Tick).await;
+    }
+}
+
 impl IRCBotClient {
     async fn connect(
         nick: String,
         secret: String,
         channel: String,
         ct: CommandTree,
-    ) -> (IRCBotClient, IRCBotMessageSender) {
+    ) -> (IRCBotClient, IRCBotMessageSender, IRCBotMessageReader) {
         // Creates the stream object that will go into the client.
         let stream = TcpStream::connect("irc.chat.twitch.tv:6667").await.unwrap();
         // Get a stream reference to use for reading.
         let reader = BufReader::new(stream.clone());
         let (s, r) = async_std::sync::channel(10); // 10 is capacity of buffer
+        let (events_s, events_r) = async_std::sync::channel(100);
+        let mut filters = FilterEngine::from_file(&config_file(&channel, "filters.json"));
+        let mut game = Game::new();
+        game.set_config(GameConfig::load(&config_file(&channel, "game.json")));
+        let mut ignore = IgnoreList::load(Path::new("ignore.json"));
+        ignore.set_nick(&nick);
+        filters.set_ignore_list(ignore);
         (
             IRCBotClient {
                 stream: stream.clone(),
                 nick: nick,
                 secret: secret,
-                reader: reader,
+                events: events_r,
+                later: events_s.clone(),
                 sender: s,
                 channel: channel,
                 ct: ct,
-                game: Game::new(),
+                filters: filters,
+                strikes: StrikeLedger::load(Path::new("strikes.json")),
+                modlog: ModLog::new(Path::new("modlog.jsonl")),
+                raid: RaidDetector::new(),
+                game: game,
+                presence: Presence::new(),
                 audio: Audio::new(),
                 autosave: false,
+                sequence: None,
             },
             IRCBotMessageSender {
                 writer: stream,
                 queue: r,
             },
+            IRCBotMessageReader {
+                reader: reader,
+                events: events_s,
+            },
         )
         // return the async class for writing back down the TcpStream instead, which contains the
         // receiver + the tcpstream clone
     }
 
     async fn authenticate(&mut self) -> () {
+        println!("Requesting capabilities...");
+        self.stream.send(TwitchFmt::cap_req()).await;
         println!("Writing password...");
         self.stream.send(TwitchFmt::pass(&self.secret)).await;
         println!("Writing nickname...");
@@ -187,7 +255,8 @@ impl IRCBotClient {
     }
     */
 
-    async fn do_command(&mut self, user: String, mut cmd: String) -> Command {
+    async fn do_command(&mut self, msg: &ChatMessage, mut cmd: String) -> Command {
+        let user = msg.name.clone();
         let format_str = format!("[Name({}),Command({})] Result: ", user, cmd);
         let log_res = |s| println!("{}{}", format_str, s);
 
@@ -195,12 +264,14 @@ impl IRCBotClient {
             Some(x) => x,
             None => {
                 log_res("Skipped as no match was found.");
-                return Command::Continue; // Not a valid command
+                return Command::Failed; // Not a valid command
             }
         };
         let args = cmd;
         println!("Arguments being returned -> '{}'", args);
-        if node.admin_only && user != "desktopfolder" {
+        let is_admin = is_admin(msg);
+        let is_mod = is_mod(msg);
+        if node.admin_only && !is_admin {
             self.sender
                 .send(TwitchFmt::privmsg(
                     &"Naughty naughty, that's not for you!".to_string(),
@@ -208,7 +279,17 @@ impl IRCBotClient {
                 ))
                 .await;
             log_res("Blocked as user is not bot administrator.");
-            return Command::Continue;
+            return Command::Failed;
+        }
+        if !node.allowed(is_admin, is_mod) {
+            self.sender
+                .send(TwitchFmt::privmsg(
+                    &"Only moderators can do that!".to_string(),
+                    &self.channel,
+                ))
+                .await;
+            log_res("Blocked as user is not a moderator.");
+            return Command::Failed;
         }
         let command = match &node.value {
             CmdValue::StringResponse(x) => {
@@ -223,7 +304,16 @@ impl IRCBotClient {
             }
             CmdValue::Alias(x) => {
                 log_res(format!("! Didn't return an alias ({}).", x).as_str());
-                return Command::Continue;
+                return Command::Failed;
+            }
+            CmdValue::Disabled => {
+                log_res("! Didn't skip a disabled command.");
+                return Command::Failed;
+            }
+            CmdValue::Sequence(steps) => {
+                log_res(format!("Running a sequence of {} command(s).", steps.len()).as_str());
+                let steps = steps.clone();
+                return self.start_sequence(msg, steps).await;
             }
             CmdValue::Generic(x) => {
                 if x.as_str() == "debug:use_internal_mapping" {
@@ -233,6 +323,17 @@ impl IRCBotClient {
                 }
             }
         };
+        // Moderation commands are for mods, even if commands.json forgets to say so.
+        if mods_only(command) && !is_mod {
+            self.sender
+                .send(TwitchFmt::privmsg(
+                    &"Only moderators can do that!".to_string(),
+                    &self.channel,
+                ))
+                .await;
+            log_res("Blocked as user is not a moderator.");
+            return Command::Failed;
+        }
         match command.as_str() {
             "meta:help" => {
                 self.sender
@@ -258,34 +359,351 @@ impl IRCBotClient {
             }
             "meta:reload_commands" => {
                 log_res("Reloaded commands from file.");
-                self.ct = CommandTree::from_json_file(Path::new("commands.json"));
+                self.ct = load_commands(&self.channel);
             }
-            "game:bet_for" => {
-                log_res("Bet that it works!");
-                match self.game.bet_for(&user, &args) {
+            "meta:reload_filters" => {
+                log_res("Reloaded filters from file.");
+                self.filters
+                    .reload_from(&config_file(&self.channel, "filters.json"));
+            }
+            "mod:strikes" => {
+                let target = args.trim_start_matches('@').to_lowercase();
+                if target.is_empty() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Whose strikes? (strikes user)".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Returned a user's strikes.");
+                let count = self
+                    .strikes
+                    .count(&target, self.filters.strike_config(), unix_now());
+                self.sender
+                    .send(TwitchFmt::privmsg(
+                        &format!("'{}' has {} strike(s).", target, count),
+                        &self.channel,
+                    ))
+                    .await;
+            }
+            "mod:clear_strikes" => {
+                let target = args.trim_start_matches('@').to_lowercase();
+                if target.is_empty() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Whose strikes should be cleared? (clear_strikes user)".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                if !self.strikes.clear(&target) {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &format!("'{}' has no strikes to clear.", target),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Cleared a user's strikes.");
+                self.strikes.save();
+                let mut entry = ModEntry::new(&target, unix_now());
+                entry.action = String::from("clear strikes");
+                entry.moderator = user.clone();
+                self.modlog.append(&entry);
+                self.sender
+                    .send(TwitchFmt::privmsg(
+                        &format!("Cleared all strikes for '{}'.", target),
+                        &self.channel,
+                    ))
+                    .await;
+            }
+            "mod:permit" => {
+                log_res("Permitted a user to post a link.");
+                let mut words = args.split_whitespace();
+                let target = words.next().unwrap_or("").trim_start_matches('@').to_lowercase();
+                let secs = words.next().and_then(|w| w.parse::<u64>().ok()).unwrap_or(60);
+                if target.is_empty() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Who should be permitted? (permit user [seconds])".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                self.filters.permit(&target, secs, unix_now());
+                let mut entry = ModEntry::new(&target, unix_now());
+                entry.action = format!("permit {}", secs);
+                entry.moderator = user.clone();
+                self.modlog.append(&entry);
+                self.sender
+                    .send(TwitchFmt::privmsg(
+                        &format!("{} may post one link in the next {} seconds.", target, secs),
+                        &self.channel,
+                    ))
+                    .await;
+            }
+            "mod:ban" | "mod:unban" | "mod:timeout" => {
+                // ban user [reason], unban user, timeout user seconds [reason]
+                let mut words = args.splitn(2, ' ');
+                let target = words.next().unwrap_or("").trim_start_matches('@').to_lowercase();
+                let mut rest = words.next().unwrap_or("").trim().to_string();
+                let secs = if command == "mod:timeout" {
+                    let mut words = rest.splitn(2, ' ');
+                    let secs = words.next().and_then(|w| w.parse::<u64>().ok());
+                    rest = words.next().unwrap_or("").trim().to_string();
+                    secs
+                } else {
+                    None
+                };
+                let reason = if rest.is_empty() {
+                    format!("Moderated by {}.", user)
+                } else {
+                    rest
+                };
+                let result = match (command.as_str(), secs) {
+                    ("mod:ban", _) => FilterResult::Ban(reason),
+                    ("mod:unban", _) => FilterResult::Unban(reason),
+                    (_, Some(secs)) => FilterResult::Timeout(secs, reason),
+                    (_, None) => FilterResult::Empty,
+                };
+                if target.is_empty() || result == FilterResult::Empty {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Usage: ban user [reason], unban user, timeout user seconds [reason]"
+                                .to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Carried out a moderation action.");
+                let mut entry = ModEntry::new(&target, unix_now());
+                entry.moderator = user.clone();
+                self.enforce(entry, None, result).await;
+            }
+            "mod:modlog" => {
+                let target = args.trim_start_matches('@').to_lowercase();
+                if target.is_empty() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Whose log? (modlog user)".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Returned a user's moderation log.");
+                let now = unix_now();
+                let entries = self.modlog.recent(&target, 3);
+                let response = if entries.is_empty() {
+                    format!("No moderation actions on record for '{}'.", target)
+                } else {
+                    format!(
+                        "Recent actions for '{}': {}",
+                        target,
+                        entries
+                            .iter()
+                            .map(|e| e.summary(now))
+                            .collect::<Vec<String>>()
+                            .join(" | ")
+                    )
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "mod:filtertest" => {
+                log_res("Tested a message against the filter.");
+                // Someone with no roles, so no rule is skipped.
+                let test = ChatMessage::new(&String::from("filtertest"), &self.channel, &args);
+                let verdict = self.filters.dry_run(&test, unix_now());
+                let mut response = match verdict.result {
+                    FilterResult::Empty => String::from("No rule matched."),
+                    FilterResult::Ban(reason) => format!(
+                        "Rule '{}' matched: strike (escalates to a ban). Reason: {}",
+                        verdict.rule, reason
+                    ),
+                    result => format!("Rule '{}' matched: {:?}", verdict.rule, result),
+                };
+                if !verdict.shadow.is_empty() {
+                    let shadow: Vec<String> =
+                        verdict.shadow.iter().map(|(r, _)| r.clone()).collect();
+                    response = format!("{} Shadow rules: {}", response, shadow.join(", "));
+                }
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "mod:lockdown" => {
+                if self.raid.locked() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Chat is already locked down.".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Locked down chat.");
+                let until = unix_now() + self.filters.raid_config().cooldown;
+                self.raid.lock(until);
+                self.lockdown(&format!("called by {}", user)).await;
+            }
+            "mod:unlock" => {
+                if !self.raid.unlock() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Chat isn't locked down.".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                log_res("Lifted a lockdown.");
+                self.lift_lockdown().await;
+            }
+            "mod:ignore" | "mod:unignore" => {
+                let target = args.trim_start_matches('@').to_lowercase();
+                if target.is_empty() {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Who should that be?".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    return Command::Failed;
+                }
+                let ignore = self.filters.ignore_list();
+                let response = if command == "mod:ignore" {
+                    log_res("Added a user to the ignore list.");
+                    ignore.add(&target);
+                    ignore.save();
+                    format!("Ignoring '{}' from now on.", target)
+                } else if ignore.remove(&target) {
+                    log_res("Removed a user from the ignore list.");
+                    ignore.save();
+                    format!("No longer ignoring '{}'.", target)
+                } else {
+                    format!("'{}' isn't on the ignore list.", target)
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "mod:ignored" => {
+                log_res("Listed ignored users.");
+                let users = self.filters.ignore_list().users();
+                let response = if users.is_empty() {
+                    String::from("Nobody is being ignored.")
+                } else {
+                    format!("Ignoring: {}", users.join(", "))
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "meta:which" => {
+                log_res("Reported which layer a command comes from.");
+                let mut query = args.clone();
+                let response = match self.ct.find(&mut query) {
+                    Some(n) => format!("'{}' is resolved from {}.", args, n.layer),
+                    None => match self.ct.disabled_by(&args) {
+                        Some(layer) => format!("'{}' is disabled by {}.", args, layer),
+                        None => format!("'{}' is not a command.", args),
+                    },
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:open" | "game:predict" | "game:lock" | "game:resolve" => {
+                let result = if command == "game:lock" {
+                    self.game.lock_round()
+                } else if command == "game:resolve" {
+                    self.game.resolve_outcome(&args)
+                } else {
+                    // open [seconds] [title], predict [seconds] title | outcome | outcome...
+                    let mut words = args.splitn(2, ' ');
+                    let window = words.next().and_then(|w| w.parse::<u64>().ok());
+                    let rest = match window {
+                        Some(_) => words.next().unwrap_or(""),
+                        None => args.as_str(),
+                    };
+                    let mut parts = rest.split('|').map(String::from);
+                    let title = parts.next().unwrap_or(String::new());
+                    let outcomes: Vec<String> = parts.collect();
+                    if command == "game:predict" && outcomes.is_empty() {
+                        Err(String::from(
+                            "Usage: predict [seconds] title | outcome | outcome ...",
+                        ))
+                    } else {
+                        self.game.open_round(&title, outcomes, window, unix_now())
+                    }
+                };
+                let response = match result {
+                    Ok(response) => {
+                        log_res("Opened, locked or resolved a betting round.");
+                        if command == "game:resolve" && self.autosave {
+                            self.game.save();
+                        }
+                        response
+                    }
                     Err(e) => {
                         self.sender
                             .send(TwitchFmt::privmsg(&e, &self.channel))
-                            .await
+                            .await;
+                        return Command::Failed;
                     }
-                    _ => {}
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:bet" => {
+                log_res("Bet on an outcome!");
+                if let Err(e) = self.game.bet(&user, &args) {
+                    self.sender
+                        .send(TwitchFmt::privmsg(&e, &self.channel))
+                        .await;
+                    return Command::Failed;
+                }
+            }
+            "game:bet_for" => {
+                log_res("Bet that it works!");
+                if let Err(e) = self.game.bet_for(&user, &args) {
+                    self.sender
+                        .send(TwitchFmt::privmsg(&e, &self.channel))
+                        .await;
+                    return Command::Failed;
                 }
             }
             "game:bet_against" => {
                 log_res("Bet that it fails!");
-                match self.game.bet_against(&user, &args) {
+                if let Err(e) = self.game.bet_against(&user, &args) {
+                    self.sender
+                        .send(TwitchFmt::privmsg(&e, &self.channel))
+                        .await;
+                    return Command::Failed;
+                }
+            }
+            "game:failed" => {
+                let response = match self.game.failed() {
+                    Ok(response) => response,
                     Err(e) => {
                         self.sender
                             .send(TwitchFmt::privmsg(&e, &self.channel))
-                            .await
+                            .await;
+                        return Command::Failed;
                     }
-                    _ => {}
-                }
-            }
-            "game:failed" => {
+                };
                 log_res("Noted that it failed.");
                 self.sender
-                    .send(TwitchFmt::privmsg(&self.game.failed(), &self.channel))
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
                     .await;
                 if self.autosave {
                     self.game.save(); // Note: This should really be done in Game's code, 
@@ -293,15 +711,171 @@ impl IRCBotClient {
                 }
             }
             "game:worked" => {
+                let response = match self.game.worked() {
+                    Ok(response) => response,
+                    Err(e) => {
+                        self.sender
+                            .send(TwitchFmt::privmsg(&e, &self.channel))
+                            .await;
+                        return Command::Failed;
+                    }
+                };
                 log_res("Noted that it succeeded!");
                 self.sender
-                    .send(TwitchFmt::privmsg(&self.game.worked(), &self.channel))
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
                     .await;
                 if self.autosave {
                     self.game.save(); // Note: This should really be done in Game's code, 
                     // this is just a rushed impl
                 }
             }
+            "game:cancel" => {
+                let response = match self.game.cancel() {
+                    Ok(response) => {
+                        log_res("Cancelled the betting round.");
+                        if self.autosave {
+                            self.game.save();
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        self.sender
+                            .send(TwitchFmt::privmsg(&e, &self.channel))
+                            .await;
+                        return Command::Failed;
+                    }
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:odds" => {
+                log_res("Returned the current odds.");
+                self.sender
+                    .send(TwitchFmt::privmsg(&self.game.odds(), &self.channel))
+                    .await;
+            }
+            "game:live" => {
+                // live on, live off, or just live to check - only mods can change it
+                let change = args.trim() == "on" || args.trim() == "off";
+                if change && !is_mod {
+                    self.sender
+                        .send(TwitchFmt::privmsg(
+                            &"Only moderators can do that!".to_string(),
+                            &self.channel,
+                        ))
+                        .await;
+                    log_res("Blocked as user is not a moderator.");
+                    return Command::Failed;
+                }
+                let response = match args.trim() {
+                    "on" => {
+                        self.presence.set_live(true);
+                        String::from("Marked the stream as live.")
+                    }
+                    "off" => {
+                        self.presence.set_live(false);
+                        String::from("Marked the stream as offline.")
+                    }
+                    _ if self.presence.live() => String::from("The stream is marked as live."),
+                    _ => String::from("The stream is marked as offline."),
+                };
+                log_res("Checked or changed whether the stream is live.");
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:give" => {
+                // give user amount
+                let mut words = args.split_whitespace();
+                let to = words.next().unwrap_or("").trim_start_matches('@').to_lowercase();
+                let amount = words.next().unwrap_or("").to_string();
+                let response = match self.game.give(&user, &to, &amount, unix_now()) {
+                    Ok(response) => {
+                        log_res("Gave points to another player.");
+                        if self.autosave {
+                            self.game.save();
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        self.sender
+                            .send(TwitchFmt::privmsg(&e, &self.channel))
+                            .await;
+                        return Command::Failed;
+                    }
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:slots" | "game:roulette" => {
+                let result = if command == "game:slots" {
+                    self.game.slots(&user, &args, unix_now())
+                } else {
+                    self.game.roulette(&user, &args, unix_now())
+                };
+                let response = match result {
+                    Ok(response) => {
+                        log_res("Played a casino game.");
+                        if self.autosave {
+                            self.game.save();
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        self.sender
+                            .send(TwitchFmt::privmsg(&e, &self.channel))
+                            .await;
+                        return Command::Failed;
+                    }
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:daily" | "game:bailout" => {
+                let result = if command == "game:daily" {
+                    self.game.daily(&user, unix_now())
+                } else {
+                    self.game.bailout(&user, unix_now())
+                };
+                let response = match result {
+                    Ok(response) => {
+                        log_res("Gave out a daily bonus or a bailout.");
+                        if self.autosave {
+                            self.game.save();
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        self.sender
+                            .send(TwitchFmt::privmsg(&e, &self.channel))
+                            .await;
+                        return Command::Failed;
+                    }
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&response, &self.channel))
+                    .await;
+            }
+            "game:leaderboard" => {
+                log_res("Returned the leaderboard.");
+                self.sender
+                    .send(TwitchFmt::privmsg(&self.game.leaderboard(&args), &self.channel))
+                    .await;
+            }
+            "game:rank" => {
+                log_res("Returned a player's rank.");
+                let query = if args == "" {
+                    user.clone()
+                } else {
+                    args.trim_start_matches('@').to_lowercase()
+                };
+                self.sender
+                    .send(TwitchFmt::privmsg(&self.game.rank(&query), &self.channel))
+                    .await;
+            }
             "game:status" => {
                 log_res("Returned a player's status.");
                 let query = if args == "" { &user } else { &args };
@@ -312,6 +886,8 @@ impl IRCBotClient {
             "game:reload" => {
                 log_res("Reloaded the game.");
                 self.game.reload();
+                self.game
+                    .set_config(GameConfig::load(&config_file(&self.channel, "game.json")));
             }
             "game:save" => {
                 log_res("Saved the game.");
@@ -330,17 +906,120 @@ impl IRCBotClient {
             }
             _ => {
                 log_res("! Not yet equipped to handle this command.");
-                return Command::Continue;
+                return Command::Failed;
             }
         }
         log_res("Successfully executed command.");
         Command::Continue
     }
 
-    async fn ban(&mut self, name: &String, reason: &String) {
-        self.sender
-            .send(TwitchFmt::privmsg(&format!("/ban {} {}", name, reason), &self.channel))
-            .await;
+    async fn start_sequence(&mut self, msg: &ChatMessage, steps: Vec<SeqStep>) -> Command {
+        match &mut self.sequence {
+            // Nested in a running sequence, which picks it up as its next steps.
+            Some(run) => {
+                if run.push(steps) {
+                    Command::Continue
+                } else {
+                    println!("Refusing to run sequence, nested too deeply.");
+                    Command::Failed
+                }
+            }
+            None => self.run_sequence(msg.clone(), SeqRun::new(steps)).await,
+        }
+    }
+
+    // Boxed, as sequences run through do_command (which may start sequences).
+    fn run_sequence(&mut self, msg: ChatMessage, run: SeqRun) -> LocalBoxFuture<'_, Command> {
+        async move {
+            self.sequence = Some(run);
+            let result = loop {
+                let next = match &mut self.sequence {
+                    Some(run) => run.advance(),
+                    None => SeqNext::Done,
+                };
+                match next {
+                    SeqNext::Run(command) => {
+                        // Every step goes through the usual permission checks.
+                        match self.do_command(&msg, command.clone()).await {
+                            Command::Continue => {}
+                            r => {
+                                println!("Sequence stopped at step '{}'.", command);
+                                break r;
+                            }
+                        }
+                    }
+                    SeqNext::Wait(delay) => {
+                        // Come back later through the event queue, so chat keeps flowing meanwhile.
+                        if let Some(run) = self.sequence.take() {
+                            let later = self.later.clone();
+                            let msg = msg.clone();
+                            task::spawn(async move {
+                                task::sleep(Duration::from_millis(delay)).await;
+                                later.send(Event::Resume(msg, run)).await;
+                            });
+                        }
+                        break Command::Continue;
+                    }
+                    SeqNext::Done => break Command::Continue,
+                }
+            };
+            self.sequence = None;
+            result
+        }
+        .boxed_local()
+    }
+
+    // Carries out (and logs) a moderation action against a user.
+    // msg_id is only needed for deletes.
+    async fn enforce(&mut self, entry: ModEntry, msg_id: Option<&String>, result: FilterResult) {
+        let name = &entry.user;
+        let message = match &result {
+            FilterResult::Warn(reason) => {
+                TwitchFmt::privmsg(&format!("@{} {}", name, reason), &self.channel)
+            }
+            FilterResult::Delete(_) => match msg_id {
+                Some(id) => TwitchFmt::delete(id, &self.channel),
+                None => {
+                    println!("[Moderation] Can't delete {}'s message without its id.", name);
+                    return;
+                }
+            },
+            FilterResult::Timeout(secs, reason) => {
+                TwitchFmt::timeout(name, *secs, reason, &self.channel)
+            }
+            FilterResult::Ban(reason) => TwitchFmt::ban(name, reason, &self.channel),
+            FilterResult::Unban(_) => TwitchFmt::unban(name, &self.channel),
+            FilterResult::Skip | FilterResult::Empty => return,
+        };
+        println!("[Moderation] {} -> {:?}", name, result);
+        self.modlog.append(&entry.with_result(&result));
+        self.sender.send(message).await;
+    }
+
+    // Returns true if the filter acted on the message.
+    async fn moderate(&mut self, msg: &ChatMessage, rule: String, result: FilterResult) -> bool {
+        let result = match result {
+            FilterResult::Empty => return false,
+            FilterResult::Ban(reason) => {
+                // Bans escalate through strikes, rather than happening on the first offense.
+                let (count, step) =
+                    self.strikes
+                        .strike(&msg.name, self.filters.strike_config(), unix_now());
+                self.strikes.save();
+                println!("User {} is now on strike {}.", msg.name, count);
+                step.result(format!("{} (Strike {})", reason, count))
+            }
+            r => r,
+        };
+        // A strike step can be set to do nothing, then the message goes through.
+        if result == FilterResult::Empty {
+            return false;
+        }
+        let mut entry = ModEntry::new(&msg.name, unix_now());
+        entry.message = msg.text.clone();
+        entry.rule = rule;
+        self.enforce(entry, msg.tag("id"), result).await;
+        true
     }
 
     async fn handle_twitch(&mut self, line: &String) -> Command {
@@ -350,62 +1029,208 @@ impl IRCBotClient {
                 self.sender.send(TwitchFmt::pong()).await;
                 Command::Continue
             }
-            _ => Command::Continue,
+            line => {
+                // Other bots (and we) don't earn points.
+                let ignore = self.filters.ignore_list();
+                match parse_membership(line) {
+                    Some(Membership::Names(users)) => self.presence.update(Membership::Names(
+                        users.into_iter().filter(|u| !ignore.contains(u)).collect(),
+                    )),
+                    Some(Membership::Join(user)) if ignore.contains(&user) => {}
+                    Some(membership) => self.presence.update(membership),
+                    None => {}
+                }
+                Command::Continue
+            }
         }
     }
 
-    async fn launch_read(&mut self) -> Result<String> {
-        lazy_static! {
-            static ref COMMAND_RE: Regex = Regex::new(r"^(bot |!|~)\s*(.+?)\s*$").unwrap();
-            static ref PRIV_RE: Regex =
-                Regex::new(r":(\w*)!\w*@\w*\.tmi\.twitch\.tv PRIVMSG #\w* :\s*(.*)").unwrap();
+    async fn lockdown(&mut self, reason: &String) {
+        let config = self.filters.raid_config().clone();
+        for command in &config.lockdown {
+            self.sender
+                .send(TwitchFmt::privmsg(command, &self.channel))
+                .await;
         }
-        let mut line = String::new();
-
-        loop {
-            line.clear();
-            match self.reader.read_line(&mut line).await {
-                Ok(_) => {
-                    println!("[Received] Message: '{}'", line.trim());
-
-                    // First, parse if it's a private message, or a skip/ping/etc.
-                    let (name, message) = match PRIV_RE.captures(line.as_str()) {
-                        // there must be a better way...
-                        Some(caps) => (caps.str_at(1), caps.str_at(2)),
-                        None => match self.handle_twitch(&line).await {
-                            Command::Stop => return Ok("Stopped due to twitch.".to_string()),
-                            _ => continue,
-                        },
-                    };
+        self.sender
+            .send(TwitchFmt::privmsg(
+                &format!(
+                    "Possible raid ({}), locking down chat for at least {} seconds. Mods, heads up!",
+                    reason, config.cooldown
+                ),
+                &self.channel,
+            ))
+            .await;
+        let mut entry = ModEntry::new(&self.channel, unix_now());
+        entry.action = String::from("lockdown");
+        entry.reason = reason.clone();
+        self.modlog.append(&entry);
+    }
 
-                    // Now we filter based on the username & the message sent.
-                    match filter(&name, &message) {
-                        FilterResult::Skip => continue,
-                        FilterResult::Ban(reason) => self.ban(&name, &reason).await,
-                        _ => {}
-                    }
+    async fn lift_lockdown(&mut self) {
+        let config = self.filters.raid_config().clone();
+        for command in &config.unlock {
+            self.sender
+                .send(TwitchFmt::privmsg(command, &self.channel))
+                .await;
+        }
+        self.sender
+            .send(TwitchFmt::privmsg(
+                &"The lockdown has been lifted, carry on!".to_string(),
+                &self.channel,
+            ))
+            .await;
+        let mut entry = ModEntry::new(&self.channel, unix_now());
+        entry.action = String::from("unlock");
+        self.modlog.append(&entry);
+    }
 
-                    // Now, we parse the command out of the message.
-                    let command = match COMMAND_RE.captures(message.as_str()) {
-                        // there must be a better way...
-                        Some(caps) => caps.str_at(2),
-                        None => continue,
-                    };
+    // Called about once a second, for anything that runs on a timer.
+    async fn tick(&mut self) {
+        if let Some(RaidEvent::Lift) = self.raid.tick(unix_now()) {
+            self.lift_lockdown().await;
+        }
+        if let Some(announcement) = self.game.tick(unix_now()) {
+            self.sender
+                .send(TwitchFmt::privmsg(&announcement, &self.channel))
+                .await;
+        }
+        let awards = self
+            .presence
+            .tick(&self.game.config().accrual, unix_now());
+        if !awards.is_empty() {
+            let total = self.game.award(&awards);
+            println!("[Game] Gave {} points to {} viewer(s).", total, awards.len());
+            if self.autosave {
+                self.game.save();
+            }
+        }
+    }
 
-                    // Finally, we actually take the command and maybe take action.
-                    if let Command::Stop = self.do_command(name, command).await {
+    async fn launch_read(&mut self) -> Result<String> {
+        loop {
+            let line = match self.events.recv().await {
+                Ok(Event::Line(line)) => line,
+                Ok(Event::Tick) => {
+                    self.tick().await;
+                    continue;
+                }
+                Ok(Event::Resume(msg, run)) => {
+                    if let Command::Stop = self.run_sequence(msg, run).await {
                         return Ok("Received stop command.".to_string());
                     }
-                }
-                Err(e) => {
-                    println!("Encountered error: {}", e);
                     continue;
                 }
+                Err(e) => return Ok(format!("No more events ({}).", e)),
+            };
+            println!("[Received] Message: '{}'", line.trim());
+
+            // First, parse if it's a private message, or a skip/ping/etc.
+            let msg = match ChatMessage::parse(line.as_str()) {
+                Some(msg) => msg,
+                None => match self.handle_twitch(&line).await {
+                    Command::Stop => return Ok("Stopped due to twitch.".to_string()),
+                    _ => continue,
+                },
+            };
+
+            // Now we filter based on the username & the message sent.
+            // Ignored users (other bots, and ourselves) are dropped here without a word.
+            // Mods testing the filter would only trip it, so that's let through untouched
+            // (unless they're ignored, like a bot with a mod badge).
+            self.filters.reload_if_changed();
+            let command = self.ct.command_in(&msg.text, &self.nick);
+            let testing = match &command {
+                Some(command) => {
+                    is_mod(&msg)
+                        && self.ct.resolves_to(command, "mod:filtertest")
+                        && !self.filters.ignore_list().contains(&msg.name)
+                }
+                None => false,
+            };
+            let verdict = if testing {
+                Verdict::new()
+            } else {
+                self.filters.judge(&msg, unix_now())
+            };
+            if verdict.result == FilterResult::Skip {
+                continue;
+            }
+
+            // Raids are judged by chat as a whole, on top of filtering single messages.
+            let raid = self
+                .raid
+                .observe(&msg, self.filters.raid_config(), unix_now());
+            if let Some(RaidEvent::Lockdown(reason)) = raid {
+                self.lockdown(&reason).await;
+            }
+
+            // Anything the filter acts on doesn't get to run commands.
+            for (rule, result) in verdict.shadow {
+                println!("[Shadow] Rule '{}' would have done {:?}", rule, result);
+                let mut entry = ModEntry::new(&msg.name, unix_now()).with_result(&result);
+                entry.action = format!("shadow {}", entry.action);
+                entry.message = msg.text.clone();
+                entry.rule = rule;
+                self.modlog.append(&entry);
+            }
+            if self.moderate(&msg, verdict.rule, verdict.result).await {
+                continue;
+            }
+            self.presence.seen(&msg);
+
+            // Now, we take the command (if any) out of the message.
+            let command = match command {
+                Some(command) => command,
+                None => continue,
+            };
+
+            // Finally, we actually take the command and maybe take action.
+            if let Command::Stop = self.do_command(&msg, command).await {
+                return Ok("Received stop command.".to_string());
             }
         }
     }
 }
 
+// Shared commands.json, with this channel's overlay on top.
+fn load_commands(channel: &String) -> CommandTree {
+    CommandTree::from_layered_files(
+        Path::new("commands.json"),
+        &channel_file(channel, "commands.json"),
+    )
+}
+
+fn is_admin(msg: &ChatMessage) -> bool {
+    msg.name == "desktopfolder"
+}
+
+fn is_mod(msg: &ChatMessage) -> bool {
+    is_admin(msg) || msg.has_any_role(&[Role::Broadcaster, Role::Moderator])
+}
+
+// Running the betting rounds is up to the mods, along with everything under "mod:".
+const MOD_COMMANDS: &[&str] = &[
+    "game:open",
+    "game:predict",
+    "game:lock",
+    "game:resolve",
+    "game:worked",
+    "game:failed",
+    "game:cancel",
+];
+
+fn mods_only(command: &str) -> bool {
+    command.starts_with("mod:") || MOD_COMMANDS.contains(&command)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 fn get_file_trimmed(filename: &str) -> String {
     match std::fs::read_to_string(filename) {
         Ok(s) => s.trim().to_string(),
@@ -421,11 +1246,15 @@ async fn async_main() {
     println!("Nick: {} | Secret: {} | Channel: {}", nick, secret, channel);
 
     // Supported commands, loaded from JSON.
-    let ct = CommandTree::from_json_file(Path::new("commands.json"));
+    let ct = load_commands(&channel);
     //ct.dump_file(Path::new("commands.parsed.json"));
-    let (mut client, mut forwarder) = IRCBotClient::connect(nick, secret, channel, ct).await;
+    let (mut client, mut forwarder, mut reader) =
+        IRCBotClient::connect(nick, secret, channel, ct).await;
     client.authenticate().await;
 
+    task::spawn(launch_tick(reader.events.clone()));
+    task::spawn(async move { reader.launch_read().await });
+
     select! {
         return_message = client.launch_read().fuse() => match return_message {
             Ok(message) => { println!("Quit (Read): {}", message); },
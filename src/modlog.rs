@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::filter::FilterResult;
+
+/* Moderation log - every moderation action, automated or not, as JSON Lines.
+ *
+ * One entry per line, appended as it happens, eg:
+ *  {"time":1597000000,"user":"spammer","message":"bigfollows dot com","rule":"links",
+ *   "action":"timeout 600","reason":"No links please.","moderator":""}
+ * An empty moderator means the filter did it.
+ */
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModEntry {
+    pub time: u64,
+    pub user: String,
+    #[serde(default = "String::new")]
+    pub message: String,
+    #[serde(default = "String::new")]
+    pub rule: String,
+    pub action: String,
+    #[serde(default = "String::new")]
+    pub reason: String,
+    #[serde(default = "String::new")]
+    pub moderator: String,
+}
+
+impl ModEntry {
+    pub fn new(user: &String, time: u64) -> ModEntry {
+        ModEntry {
+            time,
+            user: user.clone(),
+            message: String::new(),
+            rule: String::new(),
+            action: String::new(),
+            reason: String::new(),
+            moderator: String::new(),
+        }
+    }
+
+    // Fills in the action & reason from what's being done.
+    pub fn with_result(mut self, result: &FilterResult) -> ModEntry {
+        let (action, reason) = match result {
+            FilterResult::Skip => (String::from("ignore"), None),
+            FilterResult::Warn(r) => (String::from("warn"), Some(r)),
+            FilterResult::Delete(r) => (String::from("delete"), Some(r)),
+            FilterResult::Timeout(secs, r) => (format!("timeout {}", secs), Some(r)),
+            FilterResult::Ban(r) => (String::from("ban"), Some(r)),
+            FilterResult::Unban(r) => (String::from("unban"), Some(r)),
+            FilterResult::Empty => (String::from("none"), None),
+        };
+        self.action = action;
+        if let Some(r) = reason {
+            self.reason = r.clone();
+        }
+        self
+    }
+
+    pub fn summary(&self, now: u64) -> String {
+        let by = if self.moderator.is_empty() {
+            match self.rule.as_str() {
+                "" => String::from("the filter"),
+                rule => format!("rule '{}'", rule),
+            }
+        } else {
+            self.moderator.clone()
+        };
+        format!(
+            "{} by {} {}",
+            self.action,
+            by,
+            ago(now.saturating_sub(self.time))
+        )
+    }
+}
+
+fn ago(secs: u64) -> String {
+    match secs {
+        s if s < 60 => String::from("just now"),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
+pub struct ModLog {
+    path: PathBuf,
+}
+
+impl ModLog {
+    pub fn new(path: &Path) -> ModLog {
+        ModLog {
+            path: path.to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, entry: &ModEntry) -> bool {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("[ERROR] Couldn't serialize moderation entry: {}", e);
+                return false;
+            }
+        };
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+        {
+            Ok(_) => true,
+            Err(e) => {
+                println!("[ERROR] Couldn't write to the moderation log: {}", e);
+                false
+            }
+        }
+    }
+
+    // The most recent `count` entries for this user, newest first.
+    pub fn recent(&self, user: &String, count: usize) -> Vec<ModEntry> {
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(_) => return Vec::new(),
+        };
+        let mut entries: Vec<ModEntry> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|l| serde_json::from_str::<ModEntry>(&l).ok())
+            .filter(|e| e.user == *user)
+            .collect();
+        entries.reverse();
+        entries.truncate(count);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod modlog_tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_recent() {
+        let path = Path::new("test_modlog.jsonl");
+        let _ = std::fs::remove_file(path);
+        let log = ModLog::new(path);
+        let spammer = String::from("spammer");
+
+        let mut first = ModEntry::new(&spammer, 0)
+            .with_result(&FilterResult::Timeout(600, String::from("No links.")));
+        first.rule = String::from("links");
+        first.message = String::from("bigfollows dot com");
+        assert!(log.append(&first));
+        assert!(
+            log.append(&ModEntry::new(&String::from("other"), 10).with_result(&FilterResult::Skip))
+        );
+        let mut unban =
+            ModEntry::new(&spammer, 7200).with_result(&FilterResult::Unban(String::new()));
+        unban.moderator = String::from("mjb");
+        assert!(log.append(&unban));
+
+        let recent = log.recent(&spammer, 5);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].summary(7200), "unban by mjb just now");
+        assert_eq!(
+            recent[1].summary(7200),
+            "timeout 600 by rule 'links' 2h ago"
+        );
+        assert_eq!(recent[1].message, "bigfollows dot com");
+        assert_eq!(log.recent(&spammer, 1).len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+}
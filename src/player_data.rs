@@ -20,6 +20,17 @@ use serde::{Serialize, Deserialize};
 
 fn default_cash() -> i64 { 1000 }
 fn get_zero() -> i64 { 0 }
+fn get_zero_time() -> u64 { 0 }
+
+// Points given to another player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub time: u64,
+    pub to: String,
+    pub amount: i64,
+    #[serde(default = "get_zero")]
+    pub fee: i64,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Player {
@@ -30,6 +41,21 @@ pub struct Player {
     pub wins: i64,
     #[serde(default = "get_zero")]
     pub losses: i64,
+    // Rounds won in a row, and the most ever won in a row
+    #[serde(default = "get_zero")]
+    pub streak: i64,
+    #[serde(default = "get_zero")]
+    pub best_streak: i64,
+    #[serde(default = "Vec::new")]
+    pub sent: Vec<Transfer>,
+    // Unix time of the last daily bonus, and how many days in a row it's been claimed
+    #[serde(default = "get_zero_time")]
+    pub last_daily: u64,
+    #[serde(default = "get_zero")]
+    pub daily_streak: i64,
+    // Unix time of the last bankruptcy reset
+    #[serde(default = "get_zero_time")]
+    pub last_bailout: u64,
 }
 
 impl Player {
@@ -39,6 +65,12 @@ impl Player {
             cash: default_cash(),
             wins: 0,
             losses: 0,
+            streak: 0,
+            best_streak: 0,
+            sent: Vec::new(),
+            last_daily: 0,
+            daily_streak: 0,
+            last_bailout: 0,
         }
     }
 }
@@ -87,7 +119,7 @@ mod player_data_tests {
         let mut hm = std::collections::HashMap::new();
         let path = std::path::Path::new("test_player_data2.json");
 
-        let player = super::new(String::from("mjb"));
+        let player = Player::new(String::from("mjb"));
         hm.insert(player.name.clone(), player);
 
         assert!(save_players(&hm, path)); 
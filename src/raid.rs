@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+
+use crate::chat::ChatMessage;
+
+/* Raid detection - for when a wave of bot accounts hits chat at once.
+ *
+ * Per-message filtering can't keep up with dozens of accounts posting near-identical
+ * spam within seconds, so we look at chat as a whole: if too many messages arrive in
+ * `window` seconds, or too many different users post similar messages, chat gets locked
+ * down (followers-only, slow mode, ...) until `cooldown` seconds pass without a trip.
+ *
+ * Configured by the "raid" section of filters.json, off unless enabled:
+ *
+ *  "raid": { "enabled": true, "window": 10, "messages": 50, "similar_users": 8,
+ *            "similarity": 0.8, "cooldown": 300,
+ *            "lockdown": ["/followers 30m", "/slow 10"], "unlock": ["/followersoff", "/slowoff"] }
+ */
+
+fn get_false_lol() -> bool {
+    false
+}
+fn default_window() -> u64 {
+    10
+}
+fn default_messages() -> usize {
+    50
+}
+fn default_similar_users() -> usize {
+    8
+}
+fn default_similarity() -> f64 {
+    0.8
+}
+fn default_cooldown() -> u64 {
+    300
+}
+fn default_lockdown() -> Vec<String> {
+    vec![String::from("/followers 30m"), String::from("/slow 10")]
+}
+fn default_unlock() -> Vec<String> {
+    vec![String::from("/followersoff"), String::from("/slowoff")]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaidConfig {
+    #[serde(default = "get_false_lol")]
+    pub enabled: bool,
+    #[serde(default = "default_window")]
+    pub window: u64,
+    // Messages per window from anyone, 0 to not check the rate
+    #[serde(default = "default_messages")]
+    pub messages: usize,
+    // Different users posting similar messages per window, 0 to not check
+    #[serde(default = "default_similar_users")]
+    pub similar_users: usize,
+    // 0 to 1, how alike two messages have to be to count as similar
+    #[serde(default = "default_similarity")]
+    pub similarity: f64,
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    // Chat commands to lock down and unlock
+    #[serde(default = "default_lockdown")]
+    pub lockdown: Vec<String>,
+    #[serde(default = "default_unlock")]
+    pub unlock: Vec<String>,
+}
+
+impl Default for RaidConfig {
+    fn default() -> RaidConfig {
+        RaidConfig::new()
+    }
+}
+
+impl RaidConfig {
+    pub fn new() -> RaidConfig {
+        RaidConfig {
+            enabled: false,
+            window: default_window(),
+            messages: default_messages(),
+            similar_users: default_similar_users(),
+            similarity: default_similarity(),
+            cooldown: default_cooldown(),
+            lockdown: default_lockdown(),
+            unlock: default_unlock(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RaidEvent {
+    // Why we locked down
+    Lockdown(String),
+    Lift,
+}
+
+fn bigrams(text: &str) -> HashSet<(char, char)> {
+    let chars: Vec<char> = text
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+// Dice coefficient over character bigrams, 1 means the same text.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+pub struct RaidDetector {
+    recent: VecDeque<(u64, String, String)>,
+    locked_until: Option<u64>,
+}
+
+impl Default for RaidDetector {
+    fn default() -> RaidDetector {
+        RaidDetector::new()
+    }
+}
+
+impl RaidDetector {
+    pub fn new() -> RaidDetector {
+        RaidDetector {
+            recent: VecDeque::new(),
+            locked_until: None,
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked_until.is_some()
+    }
+
+    fn tripped(&self, msg: &ChatMessage, config: &RaidConfig) -> Option<String> {
+        if config.messages > 0 && self.recent.len() >= config.messages {
+            return Some(format!(
+                "{} messages in {} seconds",
+                self.recent.len(),
+                config.window
+            ));
+        }
+        if config.similar_users > 0 {
+            let users: HashSet<&String> = self
+                .recent
+                .iter()
+                .filter(|(_, _, text)| similarity(text, &msg.text) >= config.similarity)
+                .map(|(_, user, _)| user)
+                .collect();
+            if users.len() >= config.similar_users {
+                return Some(format!(
+                    "{} users posting the same thing in {} seconds",
+                    users.len(),
+                    config.window
+                ));
+            }
+        }
+        None
+    }
+
+    pub fn observe(
+        &mut self,
+        msg: &ChatMessage,
+        config: &RaidConfig,
+        now: u64,
+    ) -> Option<RaidEvent> {
+        if !config.enabled {
+            return None;
+        }
+        while let Some((time, _, _)) = self.recent.front() {
+            if time + config.window > now {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        self.recent
+            .push_back((now, msg.name.clone(), msg.text.clone()));
+        let reason = self.tripped(msg, config)?;
+        let was_locked = self.locked();
+        // Still being raided, so the lockdown lasts a while longer.
+        self.locked_until = Some(now + config.cooldown);
+        if was_locked {
+            None
+        } else {
+            Some(RaidEvent::Lockdown(reason))
+        }
+    }
+
+    pub fn lock(&mut self, until: u64) -> bool {
+        let was_locked = self.locked();
+        self.locked_until = Some(until);
+        !was_locked
+    }
+
+    pub fn unlock(&mut self) -> bool {
+        self.locked_until.take().is_some()
+    }
+
+    pub fn tick(&mut self, now: u64) -> Option<RaidEvent> {
+        match self.locked_until {
+            Some(until) if now >= until => {
+                self.locked_until = None;
+                self.recent.clear();
+                Some(RaidEvent::Lift)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod raid_tests {
+    use super::*;
+
+    fn msg(name: &str, text: &str) -> ChatMessage {
+        ChatMessage::new(
+            &String::from(name),
+            &String::from("desktopfolder"),
+            &String::from(text),
+        )
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("hello", "hello"), 1.0);
+        assert!(similarity("buy followers at bigfollows", "buy followers at bigfollowz") > 0.8);
+        assert!(similarity("buy followers at bigfollows", "good game everyone") < 0.3);
+    }
+
+    #[test]
+    fn test_similar_wave() {
+        let mut config = RaidConfig::new();
+        config.enabled = true;
+        config.similar_users = 3;
+        let mut d = RaidDetector::new();
+        assert_eq!(
+            d.observe(&msg("a", "get viewers at bigfollows 1"), &config, 0),
+            None
+        );
+        assert_eq!(d.observe(&msg("b", "hello everyone"), &config, 1), None);
+        assert_eq!(
+            d.observe(&msg("c", "get viewers at bigfollows 2"), &config, 2),
+            None
+        );
+        assert!(matches!(
+            d.observe(&msg("d", "get viewers at bigfollows 3"), &config, 3),
+            Some(RaidEvent::Lockdown(_))
+        ));
+        // Already locked, so no second lockdown, but the cooldown restarts.
+        assert_eq!(
+            d.observe(&msg("e", "get viewers at bigfollows 4"), &config, 4),
+            None
+        );
+        assert_eq!(d.tick(303), None);
+        assert_eq!(d.tick(304), Some(RaidEvent::Lift));
+        assert!(!d.locked());
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut config = RaidConfig::new();
+        config.enabled = true;
+        config.messages = 5;
+        config.similar_users = 0;
+        let mut d = RaidDetector::new();
+        for i in 0..4 {
+            assert_eq!(
+                d.observe(&msg("a", &format!("{}", i)), &config, i * 5),
+                None
+            );
+        }
+        assert!(matches!(d.observe(&msg("a", "x"), &config, 21), None));
+        for i in 0..2 {
+            assert_eq!(d.observe(&msg("a", &format!("{}", i)), &config, 22), None);
+        }
+        assert!(matches!(
+            d.observe(&msg("a", "x"), &config, 22),
+            Some(RaidEvent::Lockdown(_))
+        ));
+
+        config.enabled = false;
+        let mut d = RaidDetector::new();
+        for i in 0..10 {
+            assert_eq!(d.observe(&msg("a", "x"), &config, i), None);
+        }
+    }
+}
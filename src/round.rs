@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+
+/* Betting rounds.
+ *
+ * Bets are only taken while a round is open. A mod opens one, optionally with a title
+ * and a number of seconds to take bets for, after which it locks by itself.
+ * Once locked, nobody can bet, and the round can be resolved (worked/failed).
+ *
+ *  Closed --open--> Open --lock/timer--> Locked --worked/failed--> Closed
+ *
+ * A plain round has two outcomes, it works or it fails. Predictions have 2 to 10
+ * named outcomes instead ("Which boss kills me first? | Gwyn | Ornstein | Artorias"),
+ * which are bet on by name or number, and resolved by picking the one that happened.
+ */
+
+pub const WORKS: usize = 0;
+pub const FAILS: usize = 1;
+const MIN_OUTCOMES: usize = 2;
+const MAX_OUTCOMES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RoundState {
+    Closed,
+    Open,
+    Locked,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Round {
+    pub state: RoundState,
+    pub title: String,
+    // Unix time the round locks itself, if it was opened with a timer
+    pub closes: Option<u64>,
+    // Empty for a plain works/fails round
+    #[serde(default = "Vec::new")]
+    pub outcomes: Vec<String>,
+}
+
+impl Default for Round {
+    fn default() -> Round {
+        Round::new()
+    }
+}
+
+impl Round {
+    pub fn new() -> Round {
+        Round {
+            state: RoundState::Closed,
+            title: String::new(),
+            closes: None,
+            outcomes: Vec::new(),
+        }
+    }
+
+    // "Will it work?" if there's no title.
+    pub fn name(&self) -> String {
+        match self.title.as_str() {
+            "" => String::from("Will it work?"),
+            title => title.to_string(),
+        }
+    }
+
+    pub fn open(
+        &mut self,
+        title: &String,
+        outcomes: Vec<String>,
+        window: Option<u64>,
+        now: u64,
+    ) -> Result<(), String> {
+        match self.state {
+            RoundState::Closed => {
+                let outcomes: Vec<String> = outcomes
+                    .iter()
+                    .map(|o| o.trim().to_string())
+                    .filter(|o| !o.is_empty())
+                    .collect();
+                if !outcomes.is_empty()
+                    && (outcomes.len() < MIN_OUTCOMES || outcomes.len() > MAX_OUTCOMES)
+                {
+                    return Err(format!(
+                        "Predictions need {} to {} outcomes.",
+                        MIN_OUTCOMES, MAX_OUTCOMES
+                    ));
+                }
+                for (i, outcome) in outcomes.iter().enumerate() {
+                    if outcome.parse::<usize>().is_ok() {
+                        return Err(format!(
+                            "Outcome '{}' can't be just a number, that's how they're picked!",
+                            outcome
+                        ));
+                    }
+                    if outcomes[..i]
+                        .iter()
+                        .any(|o| o.to_lowercase() == outcome.to_lowercase())
+                    {
+                        return Err(format!("Outcome '{}' is there twice.", outcome));
+                    }
+                }
+                self.outcomes = outcomes;
+                self.state = RoundState::Open;
+                self.title = title.trim().to_string();
+                self.closes = window.map(|w| now + w);
+                Ok(())
+            }
+            RoundState::Open => Err(String::from("A betting round is already open!")),
+            RoundState::Locked => Err(String::from(
+                "The last round hasn't been resolved yet!",
+            )),
+        }
+    }
+
+    pub fn lock(&mut self) -> Result<(), String> {
+        match self.state {
+            RoundState::Open => {
+                self.state = RoundState::Locked;
+                self.closes = None;
+                Ok(())
+            }
+            RoundState::Locked => Err(String::from("Betting is already locked.")),
+            RoundState::Closed => Err(String::from("There's no betting round to lock.")),
+        }
+    }
+
+    pub fn is_prediction(&self) -> bool {
+        !self.outcomes.is_empty()
+    }
+
+    pub fn outcomes(&self) -> Vec<String> {
+        if self.is_prediction() {
+            self.outcomes.clone()
+        } else {
+            vec![String::from("works"), String::from("fails")]
+        }
+    }
+
+    // Finds an outcome by its number (starting from 1) or its name.
+    pub fn outcome(&self, query: &str) -> Option<usize> {
+        let outcomes = self.outcomes();
+        let query = query.trim();
+        match query.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= outcomes.len() => Some(n - 1),
+            Ok(_) => None,
+            Err(_) => outcomes
+                .iter()
+                .position(|o| o.to_lowercase() == query.to_lowercase()),
+        }
+    }
+
+    pub fn close(&mut self) {
+        *self = Round::new();
+    }
+
+    // Returns true if the timer ran out and the round locked itself.
+    pub fn tick(&mut self, now: u64) -> bool {
+        match (self.state, self.closes) {
+            (RoundState::Open, Some(closes)) if now >= closes => self.lock().is_ok(),
+            _ => false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state == RoundState::Open
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state == RoundState::Locked
+    }
+}
+
+#[cfg(test)]
+mod round_tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle() {
+        let mut r = Round::new();
+        assert!(r.lock().is_err());
+        assert!(r
+            .open(&String::from(" Boss fight "), Vec::new(), Some(60), 100)
+            .is_ok());
+        assert_eq!(r.name(), "Boss fight");
+        assert!(r.is_open());
+        assert!(r.open(&String::new(), Vec::new(), None, 100).is_err());
+        assert!(!r.tick(159));
+        assert!(r.tick(160));
+        assert!(r.is_locked());
+        assert!(!r.tick(1000));
+        assert!(r.open(&String::new(), Vec::new(), None, 1000).is_err());
+        r.close();
+        assert_eq!(r.state, RoundState::Closed);
+
+        // No timer means it stays open until locked.
+        assert!(r.open(&String::new(), Vec::new(), None, 0).is_ok());
+        assert_eq!(r.name(), "Will it work?");
+        assert!(!r.tick(1000000));
+        assert!(r.lock().is_ok());
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let outcomes = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        let title = String::from("Which boss kills me first?");
+        let mut r = Round::new();
+        assert_eq!(r.outcome("fails"), Some(FAILS));
+        assert!(r.open(&title, outcomes(&["Gwyn"]), None, 0).is_err());
+        assert!(r
+            .open(&title, outcomes(&["Gwyn", "gwyn"]), None, 0)
+            .is_err());
+        assert!(r.open(&title, outcomes(&["Gwyn", "2"]), None, 0).is_err());
+        assert!(r
+            .open(&title, vec![String::from("x"); 11], None, 0)
+            .is_err());
+        assert!(r
+            .open(
+                &title,
+                outcomes(&["Gwyn", " Ornstein", "Artorias"]),
+                None,
+                0
+            )
+            .is_ok());
+        assert!(r.is_prediction());
+        assert_eq!(r.outcome("ornstein"), Some(1));
+        assert_eq!(r.outcome("3"), Some(2));
+        assert_eq!(r.outcome("4"), None);
+        assert_eq!(r.outcome("0"), None);
+        assert_eq!(r.outcome("works"), None);
+    }
+}
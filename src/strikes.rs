@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::filter::Action;
+
+/* Strikes - escalating punishments for repeat offenders.
+ *
+ * Instead of banning on the first offense, each offense adds a strike,
+ * and the number of strikes picks the step to take. The defaults are
+ * warn, then a ten minute timeout, then a ban. Past the last step, the last step repeats.
+ *
+ * Strikes decay: every `decay` seconds without an offense removes one strike.
+ * The configuration lives in the "strikes" section of filters.json:
+ *
+ *  "strikes": { "steps": ["Warn", { "Timeout": 600 }, "Ban"], "decay": 604800 }
+ *
+ * The ledger itself is saved to strikes.json after every change.
+ */
+
+fn default_steps() -> Vec<Action> {
+    vec![Action::Warn, Action::Timeout(600), Action::Ban]
+}
+fn default_decay() -> u64 {
+    // One week
+    7 * 24 * 60 * 60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrikeConfig {
+    #[serde(default = "default_steps")]
+    pub steps: Vec<Action>,
+    // Seconds per strike removed, 0 means strikes never decay
+    #[serde(default = "default_decay")]
+    pub decay: u64,
+}
+
+impl Default for StrikeConfig {
+    fn default() -> StrikeConfig {
+        StrikeConfig::new()
+    }
+}
+
+impl StrikeConfig {
+    pub fn new() -> StrikeConfig {
+        StrikeConfig {
+            steps: default_steps(),
+            decay: default_decay(),
+        }
+    }
+
+    pub fn step(&self, strikes: u32) -> Action {
+        match self.steps.len() {
+            0 => Action::Ban,
+            n => self.steps[(strikes.max(1) as usize - 1).min(n - 1)].clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Strikes {
+    pub count: u32,
+    // Unix time of the most recent strike
+    pub last: u64,
+}
+
+impl Strikes {
+    fn current(&self, config: &StrikeConfig, now: u64) -> u32 {
+        if config.decay == 0 {
+            return self.count;
+        }
+        let decayed = now.saturating_sub(self.last) / config.decay;
+        self.count
+            .saturating_sub(decayed.min(u32::MAX as u64) as u32)
+    }
+}
+
+pub struct StrikeLedger {
+    path: PathBuf,
+    strikes: HashMap<String, Strikes>,
+}
+
+impl StrikeLedger {
+    pub fn load(path: &Path) -> StrikeLedger {
+        let mut ledger = StrikeLedger {
+            path: path.to_path_buf(),
+            strikes: HashMap::new(),
+        };
+        if !path.exists() {
+            return ledger;
+        }
+        let mut contents = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut contents)) {
+            Ok(_) => match serde_json::from_str(&contents) {
+                Ok(s) => ledger.strikes = s,
+                Err(e) => println!("[ERROR] Could not parse strikes: {}", e),
+            },
+            Err(e) => println!("[ERROR] Could not read strikes: {}", e),
+        }
+        ledger
+    }
+
+    pub fn save(&self) -> bool {
+        let file = match File::create(&self.path) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Could not open or create strike file! {}", e);
+                return false;
+            }
+        };
+        match serde_json::to_writer_pretty(file, &self.strikes) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("Couldn't save strikes: {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn count(&self, user: &String, config: &StrikeConfig, now: u64) -> u32 {
+        match self.strikes.get(user) {
+            Some(s) => s.current(config, now),
+            None => 0,
+        }
+    }
+
+    // Adds a strike, returning the new strike count and what to do about it.
+    pub fn strike(&mut self, user: &String, config: &StrikeConfig, now: u64) -> (u32, Action) {
+        let count = self.count(user, config, now) + 1;
+        self.strikes
+            .insert(user.clone(), Strikes { count, last: now });
+        (count, config.step(count))
+    }
+
+    pub fn clear(&mut self, user: &String) -> bool {
+        self.strikes.remove(user).is_some()
+    }
+}
+
+#[cfg(test)]
+mod strikes_tests {
+    use super::*;
+
+    fn ledger() -> StrikeLedger {
+        StrikeLedger {
+            path: PathBuf::from("test_strikes.json"),
+            strikes: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_escalation() {
+        let config = StrikeConfig::new();
+        let mut l = ledger();
+        let user = String::from("spammer");
+        assert!(matches!(l.strike(&user, &config, 100), (1, Action::Warn)));
+        assert!(matches!(
+            l.strike(&user, &config, 200),
+            (2, Action::Timeout(600))
+        ));
+        assert!(matches!(l.strike(&user, &config, 300), (3, Action::Ban)));
+        assert!(matches!(l.strike(&user, &config, 400), (4, Action::Ban)));
+        assert!(l.clear(&user));
+        assert_eq!(l.count(&user, &config, 500), 0);
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut config = StrikeConfig::new();
+        config.decay = 100;
+        let mut l = ledger();
+        let user = String::from("spammer");
+        l.strike(&user, &config, 0);
+        l.strike(&user, &config, 0);
+        assert_eq!(l.count(&user, &config, 99), 2);
+        assert_eq!(l.count(&user, &config, 100), 1);
+        assert_eq!(l.count(&user, &config, 1000), 0);
+        // Back to a warning after a long time
+        assert!(matches!(l.strike(&user, &config, 1000), (1, Action::Warn)));
+        config.decay = 0;
+        assert_eq!(l.count(&user, &config, 1000000), 1);
+    }
+}